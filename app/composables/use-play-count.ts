@@ -92,43 +92,6 @@ export const usePlayCount = createSharedComposable(() => {
     void queue.addAll(tasks)
   }
 
-  async function incrementPlayCount(track: TrackListEntry) {
-    if (!track.tags.TPE1 || !track.tags.TIT2)
-      return
-
-    const key = getTrackKey(track)
-    if (!key)
-      return
-
-    const exists = await $db()
-      .selectFrom('track_play_count')
-      .where('id_hash', '=', key)
-      .selectAll()
-      .executeTakeFirst()
-
-    if (!exists) {
-      await $db()
-        .insertInto('track_play_count')
-        .values({
-          id_hash: key,
-          last_updated_from: 'local',
-          play_count: 1,
-        })
-        .execute()
-    }
-    else {
-      await $db()
-        .updateTable('track_play_count')
-        .set({
-          play_count: exists.play_count + 1,
-        })
-        .where('id_hash', '=', key)
-        .execute()
-    }
-
-    await refreshTrackData(track.path)
-  }
-
   function canProcessTrack(track: TrackListEntry): track is TrackListEntry & { tags: { TPE1: string, TIT2: string } } {
     return !!(lastFmProfile && track.valid && track.tags.TPE1 && track.tags.TIT2)
   }
@@ -153,7 +116,6 @@ export const usePlayCount = createSharedComposable(() => {
 
   return {
     currentlyUpdatingPlayCount,
-    incrementPlayCount,
     isUpdatingPlayCount,
     updatePlayCount,
   }
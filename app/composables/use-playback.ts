@@ -3,7 +3,7 @@ export const usePlayback = createSharedComposable(() => {
   const { scrobbleTrack, updateNowPlaying } = useLastFm()
   const { getTrackData, refreshTrackData, trackCache } = useTrackData()
   const { emitMessage } = useConsole()
-  const { incrementPlayCount, updatePlayCount } = usePlayCount()
+  const { updatePlayCount } = usePlayCount()
 
   // internal
   const _playbackStatus = ref<StreamStatus | null>(prefs.get('playback-status') as StreamStatus | null)
@@ -22,6 +22,11 @@ export const usePlayback = createSharedComposable(() => {
     return { ...fileEntry, ..._currentTrackContext.value, tags: { ...fileEntry.tags } }
   })
 
+  // plays are counted by the audio thread, only the shown count needs a refresh
+  void events.playCounted.listen((event) => {
+    void refreshTrackData(event.payload.path)
+  })
+
   let timeListenedMs = 0
   let hasScrobbled = false
   const canScrobble = () => {
@@ -72,7 +77,6 @@ export const usePlayback = createSharedComposable(() => {
         // await to prevent race condition
         await nextTick()
       }
-      incrementPlayCount(_currentTrackContext.value)
       _playbackStatus.value.position = 0
 
       // if not looping, stop playback & reset current track
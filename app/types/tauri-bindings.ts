@@ -53,7 +53,18 @@ async controlPlayback(action: StreamAction) : Promise<Result<StreamStatus, Error
     else return { status: "error", error: e  as any };
 }
 },
-async getWaveform(path: string, binSize: number) : Promise<Result<number[], Error>> {
+/**
+ * The play queue as the audio thread has it.
+ */
+async getQueue() : Promise<PlayQueue> {
+    return await TAURI_INVOKE("get_queue");
+},
+/**
+ * Mono min/max pairs of `path`, emitting `WaveformProgress` as decoding
+ * goes so the waveform can fill in left to right. A request for another path
+ * cancels this one, which then returns `None`.
+ */
+async getWaveform(path: string, binSize: number) : Promise<Result<number[] | null, Error>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("get_waveform", { path, binSize }) };
 } catch (e) {
@@ -61,6 +72,49 @@ async getWaveform(path: string, binSize: number) : Promise<Result<number[], Erro
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Peaks between `start` and `end` seconds at `pixels_per_second`, one bin
+ * per pixel. Zoomed out, bins come from a cached peak pyramid; zoomed in
+ * past its finest level the range is decoded directly, so every zoom level
+ * shows real peaks rather than interpolation.
+ */
+async getWaveformRange(path: string, start: number, end: number, pixelsPerSecond: number) : Promise<Result<WaveformRange, Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_waveform_range", { path, start, end, pixelsPerSecond }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Deletes every cached waveform, peak pyramid and spectrogram. Returns how
+ * many cache files were removed.
+ */
+async purgeWaveformCache() : Promise<Result<number, Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("purge_waveform_cache") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Fills the waveform and thumbnail caches for every track in the library
+ * folders in the background, emitting `PregenProgress` as it goes. Tracks
+ * already cached are skipped, and the job stops once the waveform cache is
+ * close to full. A new job replaces the one running.
+ */
+async startPregen(settings: PregenSettings) : Promise<Result<null, Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("start_pregen", { settings }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async stopPregen() : Promise<void> {
+    await TAURI_INVOKE("stop_pregen");
+},
 async openLastfmAuth() : Promise<Result<string, Error>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("open_lastfm_auth") };
@@ -140,12 +194,154 @@ async writeId3Frames(filePath: string, targetTag: TagTypeArg, args: FrameArgs[])
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
+},
+/**
+ * Writes `args` to the file's `target_tag`, with the frame IDs `FileEntry.tags`
+ * uses. ID3v2 frames are written as they are, Vorbis comments and MP4 atoms
+ * are mapped from them and anything unmapped is written under its own name.
+ * Empty values remove the field. Only the edited tag is rewritten, so other
+ * metadata, embedded pictures included, stays as it was.
+ */
+async writeTags(filePath: string, targetTag: TagTypeArg, args: FrameArgs[]) : Promise<Result<null, Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("write_tags", { filePath, targetTag, args }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Runs an EBU R128 analysis over `paths`. With `album`, the tracks are also
+ * measured together as one album. With `write_tags`, ReplayGain 2.0 frames
+ * are written to files that carry ID3 tags; other files are skipped. A file
+ * that fails is reported in its own entry instead of failing the batch.
+ */
+async analyzeLoudness(paths: string[], album: boolean, writeTags: boolean) : Promise<Result<LoudnessReport, Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("analyze_loudness", { paths, album, writeTags }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async listOutputDevices() : Promise<Result<OutputDevice[], Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("list_output_devices") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Distinct tracks, most recently played first.
+ */
+async getRecentlyPlayed(limit: number) : Promise<Result<RecentTrack[], Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_recently_played", { limit }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Tracks with the most listens started between `from` and `to`, in unix
+ * seconds.
+ */
+async getMostPlayed(from: number, to: number, limit: number) : Promise<Result<TrackPlays[], Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_most_played", { from, to, limit }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Every listen of a track, oldest first.
+ */
+async getTrackListens(path: string) : Promise<Result<Listen[], Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_track_listens", { path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Streams meter packets to `channel` until `unsubscribe_meter` is called or
+ * the channel goes away. A new subscription replaces the previous one.
+ */
+async subscribeMeter(settings: MeterSettings, channel: TAURI_CHANNEL<MeterPacket>) : Promise<Result<null, Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("subscribe_meter", { settings, channel }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async unsubscribeMeter() : Promise<void> {
+    await TAURI_INVOKE("unsubscribe_meter");
+},
+/**
+ * Renders a spectrogram of `path` to PNG and returns a `spectrogram://` URI
+ * for it. Images are cached per file state and settings.
+ */
+async getSpectrogram(path: string, settings: SpectrogramSettings) : Promise<Result<string, Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_spectrogram", { path, settings }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Analyses one lossless file.
+ */
+async analyzeLossy(path: string) : Promise<Result<LossyReport, Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("analyze_lossy", { path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Analyses every lossless file under `folder`, emitting `LossyProgress` after
+ * each one. Files that fail to decode are logged and left out.
+ */
+async analyzeLossyFolder(folder: string) : Promise<Result<LossyReport[], Error>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("analyze_lossy_folder", { folder }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 }
 }
 
 /** user-defined events **/
 
 
+export const events = __makeEvents__<{
+lossyProgress: LossyProgress,
+playCounted: PlayCounted,
+playbackError: PlaybackError,
+playbackPosition: PlaybackPosition,
+pregenProgress: PregenProgress,
+queueChanged: QueueChanged,
+stateChanged: StateChanged,
+trackEnded: TrackEnded,
+waveformProgress: WaveformProgress
+}>({
+lossyProgress: "lossy-progress",
+playCounted: "play-counted",
+playbackError: "playback-error",
+playbackPosition: "playback-position",
+pregenProgress: "pregen-progress",
+queueChanged: "queue-changed",
+stateChanged: "state-changed",
+trackEnded: "track-ended",
+waveformProgress: "waveform-progress"
+})
 
 /** user-defined constants **/
 
@@ -153,17 +349,422 @@ async writeId3Frames(filePath: string, targetTag: TagTypeArg, args: FrameArgs[])
 
 /** user-defined types **/
 
-export type Error = { type: "Audio"; data: string } | { type: "Backend"; data: string } | { type: "Id3"; data: string } | { type: "FileSystem"; data: string } | { type: "LastFm"; data: string } | { type: "Waveform"; data: string } | { type: "Sql"; data: string } | { type: "Store"; data: string } | { type: "Stronghold"; data: string } | { type: "Other"; data: string }
+export type BandEnergy = { from: number; to: number; 
+/**
+ * Average level relative to the 1-4 kHz reference, in dB.
+ */
+level: number }
+/**
+ * Min, max and RMS of one channel, one entry per bin.
+ */
+export type ChannelPeaks = { min: number[]; max: number[]; rms: number[] }
+export type ColorMap = "Grayscale" | "Inferno" | "Viridis"
+export type CrossfadeSettings = { 
+/**
+ * Overlap between consecutive tracks in seconds. `0.0` keeps the gapless hand-over.
+ */
+duration: number; curve: FadeCurve }
+export type EqBand = { kind: EqBandKind; 
+/**
+ * Center or cutoff frequency in Hz.
+ */
+frequency: number; 
+/**
+ * Boost or cut in dB. Ignored by low-pass and high-pass bands.
+ */
+gain: number; q: number }
+export type EqBandKind = "Peaking" | "LowShelf" | "HighShelf" | "LowPass" | "HighPass"
+export type EqPreset = { name: string; settings: EqualizerSettings }
+export type EqualizerSettings = { 
+/**
+ * Bypasses the bands and preamp when off. Balance always applies.
+ */
+enabled: boolean; 
+/**
+ * Gain in dB applied before the bands.
+ */
+preamp: number; 
+/**
+ * Stereo balance from `-1.0` (left) to `1.0` (right).
+ */
+balance: number; bands: EqBand[] }
+export type Error = { type: "Audio"; data: string } | { type: "Backend"; data: string } | { type: "Id3"; data: string } | { type: "Tag"; data: string } | { type: "FileSystem"; data: string } | { type: "LastFm"; data: string } | { type: "Waveform"; data: string } | { type: "Cancelled"; data: string } | { type: "Sql"; data: string } | { type: "Store"; data: string } | { type: "Stronghold"; data: string } | { type: "Other"; data: string }
+export type FadeCurve = "Linear" | "EaseIn" | "EaseOut" | "EaseInOut"
 export type FileEntry = { path: string; name: string; filename: string; tags: Partial<{ [key in string]: string }>; thumbnail_uri: string; full_uri: string; is_playlist_track: boolean; valid: boolean; primary_tag: TagTypeArg | null; extension: string; duration: number; play_count: number }
 export type FrameArgs = { frame: string; value: string }
+export type FrequencyScale = "Linear" | "Log"
+export type Listen = { path: string; 
+/**
+ * Identity key from title and artist, shared with play counts.
+ */
+track_key: string | null; 
+/**
+ * Unix time in seconds.
+ */
+started_at: number; 
+/**
+ * Seconds of the track actually heard, excluding skipped parts.
+ */
+listened: number; duration: number; 
+/**
+ * The track played through to its end.
+ */
+completed: boolean; source: PlaySource | null }
+/**
+ * Section of the current track to loop, in seconds.
+ */
+export type LoopRegion = { start: number; end: number }
+export type LossyProgress = { folder: string; path: string; done: number; total: number }
+export type LossyReport = { path: string; verdict: LossyVerdict; 
+/**
+ * Frequency of the lowpass cliff for lossy files, otherwise the highest
+ * frequency with content. `None` when nothing clears the floor.
+ */
+cutoff: number | null; 
+/**
+ * Drop across the cutoff in dB. Large drops mean a steep filter.
+ */
+cliff: number; 
+/**
+ * How sure the verdict is, from `0` to `1`.
+ */
+confidence: number; sample_rate: number; bands: BandEnergy[] }
+export type LossyVerdict = 
+/**
+ * Content reaches the top of the spectrum without a lowpass cliff.
+ */
+"Lossless" | 
+/**
+ * A brick-wall cutoff like the lowpass of a lossy encoder.
+ */
+"Lossy" | 
+/**
+ * Too little high-frequency content to tell, e.g. quiet or dull recordings.
+ */
+"Inconclusive"
+export type LoudnessReport = { tracks: TrackLoudness[]; 
+/**
+ * All measured tracks as one album, when requested.
+ */
+album: LoudnessStats | null }
+export type LoudnessStats = { 
+/**
+ * Integrated loudness in LUFS. `None` for digital silence.
+ */
+integrated: number | null; 
+/**
+ * Loudness range in LU.
+ */
+loudness_range: number; 
+/**
+ * Highest true peak over all channels, as linear amplitude.
+ */
+true_peak: number; 
+/**
+ * ReplayGain 2.0 gain in dB.
+ */
+gain: number | null }
+/**
+ * Binary meter packet, received as an `ArrayBuffer` of little-endian `f32`s:
+ * sample rate, peak left, peak right, RMS left, RMS right, then
+ * `fft_size / 2` spectrum bins in dBFS from 0 Hz up. Levels are linear and
+ * cover everything played since the previous packet.
+ */
+export type MeterPacket = number[]
+export type MeterSettings = { 
+/**
+ * Packets per second.
+ */
+rate: number; 
+/**
+ * FFT length in frames, rounded to a power of two.
+ */
+fft_size: number }
+export type OutputDevice = { 
+/**
+ * Stable identifier, passed back through `StreamAction::SetOutputDevice`.
+ */
+id: string; name: string; is_default: boolean; 
+/**
+ * Rate the device runs at by default, used unless
+ * `OutputSettings::sample_rate` picks another one.
+ */
+sample_rate: number | null; 
+/**
+ * Common rates the device supports, for `StreamAction::SetSampleRate`.
+ */
+sample_rates: number[] }
+export type OutputSettings = { 
+/**
+ * Id from `list_output_devices`. `None` follows the system default.
+ */
+device: string | null; 
+/**
+ * Device buffer size in frames. `None` lets the driver decide.
+ */
+buffer_size: number | null; 
+/**
+ * Output rate in Hz, one of `OutputDevice::sample_rates`. `None` uses the
+ * device's default rate.
+ */
+sample_rate?: number | null }
 export type PlayCountResponse = { track: Track }
+/**
+ * When a listen counts as a play, following the usual scrobble rules: long
+ * enough tracks count once either threshold is reached.
+ */
+export type PlayCountSettings = { enabled: boolean; 
+/**
+ * Share of the track in percent.
+ */
+min_percent: number; 
+/**
+ * Seconds listened, for tracks where the percentage would take very long.
+ */
+min_seconds: number; 
+/**
+ * Tracks up to this many seconds long never count.
+ */
+min_duration: number }
+/**
+ * A listen reached the play count thresholds and the stored count went up.
+ */
+export type PlayCounted = { path: string; play_count: number }
+/**
+ * Play queue owned by the audio thread, so playback keeps advancing when the
+ * webview is throttled or reloaded.
+ */
+export type PlayQueue = { tracks: string[]; 
+/**
+ * Index into `tracks` of the loaded track.
+ */
+current: number | null; repeat: RepeatMode; 
+/**
+ * Seed of the last shuffle, so an order can be reproduced.
+ */
+shuffle_seed: number | null; 
+/**
+ * Previously played paths, most recent last.
+ */
+history: string[] }
+/**
+ * Same pairs as `library_tracks_source`.
+ */
+export type PlaySource = { source_type: PlaySourceType; 
+/**
+ * Folder path or playlist id.
+ */
+source_id: string }
+export type PlaySourceType = "folder" | "playlist"
+export type PlaybackError = { path: string | null; message: string }
+export type PlaybackPosition = { path: string | null; position: number; duration: number }
+/**
+ * Emitted after each file of a pre-generation job.
+ */
+export type PregenProgress = { path: string; done: number; total: number; 
+/**
+ * The file couldn't be decoded and was skipped.
+ */
+failed: boolean }
+export type PregenSettings = { 
+/**
+ * Files processed at once, one by default and never more than half the
+ * cores, so the rest of the app stays responsive.
+ */
+workers?: number; 
+/**
+ * Hold off while audio is playing, including halfway through a file.
+ */
+pause_while_playing: boolean }
+/**
+ * Emitted with the whole queue whenever it changes.
+ */
+export type QueueChanged = PlayQueue
+/**
+ * The part of the queue carried by `StreamStatus`. The tracks themselves
+ * come from `get_queue` and `QueueChanged`.
+ */
+export type QueueStatus = { 
+/**
+ * Index of the loaded track in the queue.
+ */
+current: number | null; repeat: RepeatMode; len?: number }
+export type RecentTrack = { path: string; 
+/**
+ * Unix time in seconds.
+ */
+last_played: number }
+export type RepeatMode = "Off" | "One" | "All"
+export type ReplayGainMode = "Off" | "Track" | "Album"
+export type ReplayGainSettings = { mode: ReplayGainMode; 
+/**
+ * Extra gain in dB, applied to tagged and untagged tracks alike.
+ */
+preamp: number; 
+/**
+ * Lowers the gain so the tagged peak doesn't go over full scale.
+ */
+prevent_clipping: boolean }
+/**
+ * Remembering where long tracks were left, e.g. audiobooks and lectures.
+ */
+export type ResumeSettings = { enabled: boolean; 
+/**
+ * Tracks shorter than this many seconds always start from the top.
+ */
+min_duration: number }
 export type SerializedOfflineScrobble = { scrobble: SerializedScrobble; timestamp: number }
 export type SerializedScrobble = { artist: string; track: string; album: string | null; track_number: number | null; duration: number; album_artist: string | null }
 export type SerializedScrobbleResponse = { accepted: number; ignored: number }
-export type StreamAction = { Play: string } | "Pause" | "Resume" | { Seek: number } | { SetLoop: boolean } | { SetVolume: number } | "ToggleMute" | "Reset"
-export type StreamStatus = { is_playing: boolean; position: number; duration: number; is_looping: boolean; path: string | null; volume: number; is_muted: boolean }
-export type TagTypeArg = "id3v2.2" | "id3v2.3" | "id3v2.4"
+export type SleepTimer = { mode: SleepTimerMode; 
+/**
+ * Seconds to fade out over before stopping. `0.0` stops abruptly.
+ */
+fade_out: number }
+export type SleepTimerMode = 
+/**
+ * Pauses once this many minutes have passed.
+ */
+{ Minutes: number } | 
+/**
+ * Stops after this many tracks have finished, counting the current one.
+ */
+{ Tracks: number } | "EndOfTrack" | 
+/**
+ * Stops when the next track in the queue belongs to another album.
+ */
+"EndOfAlbum"
+export type SleepTimerStatus = { timer: SleepTimer; 
+/**
+ * When a `Minutes` timer runs out, in milliseconds since the unix epoch.
+ */
+ends_at: number | null; 
+/**
+ * Tracks still to finish, counting the current one.
+ */
+tracks_left: number | null }
+export type SpectrogramSettings = { window: SpectrogramWindow; 
+/**
+ * Rounded to a power of two. Larger sizes resolve frequency more finely.
+ */
+fft_size: number; 
+/**
+ * Levels at or below `min_db` dBFS are drawn as the darkest colour.
+ */
+min_db: number; max_db: number; color_map: ColorMap; frequency_scale: FrequencyScale; 
+/**
+ * One column per window, spread evenly over the track.
+ */
+width: number; height: number }
+export type SpectrogramWindow = "Hann" | "Hamming" | "Blackman" | "Rectangular"
+export type StateChanged = StreamStatus
+export type StreamAction = { Play: string } | 
+/**
+ * Like `Play`, but starts from the track's saved resume position if any.
+ */
+{ PlayResumed: string } | { Enqueue: string } | { QueueAppend: string[] } | { QueueInsertNext: string[] } | { QueueRemove: number } | { QueueMove: { from: number; to: number } } | "QueueClear" | { QueueShuffle: number | null } | { QueuePlay: number } | { SetRepeat: RepeatMode } | "Next" | "Previous" | { SetPositionInterval: number } | "Pause" | "Resume" | { Seek: number } | { SetLoop: boolean } | { SetLoopRegion: { start: number; end: number } } | "ClearLoopRegion" | { SetCrossfade: CrossfadeSettings } | { SetReplayGain: ReplayGainSettings } | { SetEqualizer: EqualizerSettings } | { SaveEqPreset: string } | { LoadEqPreset: string } | { DeleteEqPreset: string } | { SetVolume: number } | { SetPlaybackRate: number } | { SetPreservePitch: boolean } | { SetOutputDevice: string | null } | { SetBufferSize: number | null } | { SetSampleRate: number | null } | { SetSleepTimer: SleepTimer } | "CancelSleepTimer" | { SetFades: TransportFades } | { SetStaticMemoryBudget: number } | { SetResume: ResumeSettings } | 
+/**
+ * Where the tracks being played were picked from, recorded with each listen.
+ */
+{ SetSource: PlaySource | null } | { SetPlayCount: PlayCountSettings } | "ToggleMute" | "Reset"
+export type StreamStatus = { is_playing: boolean; position: number; duration: number; is_looping: boolean; path: string | null; volume: number; is_muted: boolean; next_path?: string | null; crossfade?: CrossfadeSettings; 
+/**
+ * Path of the track fading out while a crossfade is in progress. `path`
+ * already points at the incoming track.
+ */
+crossfading_from?: string | null; queue?: QueueStatus; 
+/**
+ * How often `PlaybackPosition` is emitted while playing, in milliseconds.
+ * `0` turns the event off.
+ */
+position_interval?: number; replay_gain?: ReplayGainSettings; 
+/**
+ * ReplayGain offset in dB applied to the loaded track on top of `volume`.
+ */
+applied_gain?: number; equalizer?: EqualizerSettings; eq_presets?: EqPreset[]; 
+/**
+ * Speed multiplier. `position` and `duration` stay in track time.
+ */
+playback_rate?: number; 
+/**
+ * Keeps the original pitch when `playback_rate` isn't `1.0`.
+ */
+preserve_pitch?: boolean; 
+/**
+ * Loops this section instead of the whole track while `is_looping` is set.
+ * Cleared when another track is loaded.
+ */
+loop_region?: LoopRegion | null; output?: OutputSettings; 
+/**
+ * The selected output device is missing and the default one is used until
+ * it comes back.
+ */
+output_fallback?: boolean; sleep_timer?: SleepTimerStatus | null; fades?: TransportFades; 
+/**
+ * MiB a track may take once fully decoded for instant seeking. Longer
+ * tracks seek through the stream instead. `0` never decodes fully.
+ */
+static_memory_budget?: number; resume?: ResumeSettings; source?: PlaySource | null; play_count?: PlayCountSettings }
+export type TagTypeArg = "ape" | "id3v1" | "id3v2.2" | "id3v2.3" | "id3v2.4" | "mp4Ilst" | "vorbisComments" | "riffInfo" | "aiffText"
 export type Track = { playcount: string }
+/**
+ * A track played through to its end, as opposed to being stopped or replaced.
+ */
+export type TrackEnded = { path: string }
+export type TrackLoudness = { path: string; 
+/**
+ * `None` when the track couldn't be measured, see `error`.
+ */
+stats: LoudnessStats | null; tags_written: boolean; 
+/**
+ * Why the track couldn't be measured or tagged. The rest of the batch
+ * goes on without it.
+ */
+error: string | null }
+export type TrackPlays = { path: string; plays: number; listened: number }
+/**
+ * Fade lengths in seconds for transport actions. `0.0` switches instantly and
+ * leaves the samples untouched.
+ */
+export type TransportFades = { pause: number; resume: number; 
+/**
+ * Used when a track is stopped or replaced by another one.
+ */
+stop: number; 
+/**
+ * Fades out, jumps once silent and fades back in, so the jump is delayed
+ * by this long.
+ */
+seek: number }
+/**
+ * Peaks decoded so far by `get_waveform`, in its min/max pair format.
+ */
+export type WaveformProgress = { path: string; 
+/**
+ * Index of the first pair in `peaks`.
+ */
+offset: number; 
+/**
+ * Pairs completed since the previous event.
+ */
+peaks: number[]; 
+/**
+ * Pairs expected once the whole file is decoded.
+ */
+total: number }
+export type WaveformRange = { sample_rate: number; 
+/**
+ * Time of the first bin, in seconds.
+ */
+start: number; 
+/**
+ * Seconds covered by each bin.
+ */
+bin_duration: number; 
+/**
+ * One entry per channel of the file.
+ */
+channels: ChannelPeaks[] }
 
 /** tauri-specta globals **/
 
@@ -0,0 +1,108 @@
+//! Peak heap use of seeking with a fully decoded copy of the track versus
+//! seeking the stream directly.
+//!
+//! cargo bench --bench seek_memory -- path/to/long-track.flac
+
+#![allow(dead_code)]
+
+#[path = "../src/error.rs"]
+mod error;
+
+#[path = "../src/decoder.rs"]
+mod decoder;
+
+use decoder::{load_static_data, GaplessDecoder};
+use kira::sound::streaming::Decoder;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+const SEEK_COUNT: usize = 20;
+
+struct PeakAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let ptr = System.alloc(layout);
+    if !ptr.is_null() {
+      let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+      PEAK.fetch_max(current, Ordering::Relaxed);
+    }
+
+    return ptr;
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    System.dealloc(ptr, layout);
+    CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+  }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+/// Runs `f`, returning the heap peak it reached above the starting point.
+fn measure(f: impl FnOnce()) -> usize {
+  let start = CURRENT.load(Ordering::Relaxed);
+  PEAK.store(start, Ordering::Relaxed);
+
+  f();
+
+  return PEAK.load(Ordering::Relaxed) - start;
+}
+
+fn mib(bytes: usize) -> f64 {
+  return bytes as f64 / 1024.0 / 1024.0;
+}
+
+fn main() {
+  let Some(path) = std::env::args().skip(1).find(|arg| !arg.starts_with('-')) else {
+    eprintln!("usage: cargo bench --bench seek_memory -- <audio file>");
+    return;
+  };
+
+  let num_frames = GaplessDecoder::from_file(&path)
+    .expect("failed to open file")
+    .num_frames();
+  let targets: Vec<usize> = (0..SEEK_COUNT)
+    .map(|i| num_frames / SEEK_COUNT * i)
+    .collect();
+
+  // before: the loader thread decodes everything, seeks then land in memory
+  let started = Instant::now();
+  let full_peak = measure(|| {
+    let data = load_static_data(&path, usize::MAX)
+      .expect("failed to decode file")
+      .expect("no memory budget");
+    for &target in targets.iter() {
+      std::hint::black_box(data.frames.get(target));
+    }
+  });
+  let full_time = started.elapsed();
+
+  // after: every seek goes through the container's seek index
+  let started = Instant::now();
+  let stream_peak = measure(|| {
+    let mut decoder = GaplessDecoder::from_file(&path).expect("failed to open file");
+    for &target in targets.iter() {
+      decoder.seek(target).expect("failed to seek");
+      std::hint::black_box(decoder.decode().expect("failed to decode"));
+    }
+  });
+  let stream_time = started.elapsed();
+
+  println!("{} frames, {} seeks", num_frames, SEEK_COUNT);
+  println!(
+    "full decode: peak {:>8.1} MiB, {:>8.1} ms",
+    mib(full_peak),
+    full_time.as_secs_f64() * 1000.0
+  );
+  println!(
+    "stream seek: peak {:>8.1} MiB, {:>8.1} ms",
+    mib(stream_peak),
+    stream_time.as_secs_f64() * 1000.0
+  );
+}
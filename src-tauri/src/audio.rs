@@ -1,15 +1,29 @@
+use crate::decoder::{load_static_data, GaplessDecoder};
+use crate::equalizer::{sanitize_settings, Equalizer};
 use crate::error::{emit_error, Error, Result};
-use crate::playback::{StreamAction, StreamStatus};
+use crate::history::{ActiveListen, History};
+use crate::output::{check_sample_rate, find_output_device, open_audio_manager};
+use crate::playback::{
+  save_queue, CrossfadeSettings, EqPreset, EqualizerSettings, FadeCurve, LoopRegion,
+  OutputSettings, PlayCounted, PlayQueue, PlaybackError, PlaybackPosition, QueueChanged,
+  QueueStatus, ReplayGainSettings, ResumeSettings, SleepTimer, SleepTimerMode, SleepTimerStatus,
+  StateChanged, StreamAction, StreamStatus, TrackEnded, TransportFades, QUEUE,
+};
+use crate::read::{get_album_key, get_replay_gain, ReplayGain};
+use crate::resume::ResumeStore;
+use kira::clock::{ClockHandle, ClockSpeed, ClockTime};
 use kira::sound::static_sound::{StaticSoundData, StaticSoundHandle};
 use kira::sound::streaming::{StreamingSoundHandle, StreamingSoundSettings};
-use kira::sound::{FromFileError, PlaybackPosition, Region};
-use kira::{
-  self, sound::streaming::StreamingSoundData, AudioManager, AudioManagerSettings, DefaultBackend,
-};
+use kira::sound::{FromFileError, PlaybackRate, PlaybackState, Region};
+use kira::track::TrackHandle;
+use kira::{self, sound::streaming::StreamingSoundData, AudioManager, DefaultBackend};
 use kira::{Decibels, Easing, StartTime, Tween, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tauri::AppHandle;
+use tauri_specta::Event;
 use tokio::sync::{mpsc, oneshot};
 
 const TWEEN: Tween = Tween {
@@ -18,12 +32,36 @@ const TWEEN: Tween = Tween {
   start_time: StartTime::Immediate,
 };
 
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+// high enough that one tick is shorter than one frame at common sample rates
+const TRANSPORT_TICKS_PER_SECOND: f64 = 48_000.0;
+// delay applied to newly played tracks so their start tick is known exactly
+const TRANSPORT_START_LEAD: f64 = 0.05;
+
+// how often a selected output device is checked for having gone or come back
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+const MIN_PLAYBACK_RATE: f64 = 0.5;
+const MAX_PLAYBACK_RATE: f64 = 2.0;
+
+/// Mirrors `StreamStatus::is_playing` for work that should keep out of the
+/// way of playback, updated whenever the state is emitted.
+pub static IS_PLAYING: AtomicBool = AtomicBool::new(false);
+
+/// Disk write handed off by the audio thread.
+type DiskJob = Box<dyn FnOnce() + Send>;
+
 enum InternalEvent {
   Command(StreamAction, oneshot::Sender<StreamStatus>),
   LoadFinished {
     id: i32,
     data: Option<StaticSoundData>,
   },
+  NextOpened {
+    path: String,
+    opened: Result<OpenedTrack>,
+  },
+  Tick,
 }
 
 enum CurrentHandle {
@@ -33,27 +71,27 @@ enum CurrentHandle {
 }
 
 impl CurrentHandle {
-  fn stop(&mut self) {
+  fn stop(&mut self, tween: Tween) {
     match self {
       CurrentHandle::None => {}
-      CurrentHandle::Streaming(h) => h.stop(TWEEN),
-      CurrentHandle::Static(h) => h.stop(TWEEN),
+      CurrentHandle::Streaming(h) => h.stop(tween),
+      CurrentHandle::Static(h) => h.stop(tween),
     }
   }
 
-  fn pause(&mut self) {
+  fn pause(&mut self, tween: Tween) {
     match self {
       CurrentHandle::None => {}
-      CurrentHandle::Streaming(h) => h.pause(TWEEN),
-      CurrentHandle::Static(h) => h.pause(TWEEN),
+      CurrentHandle::Streaming(h) => h.pause(tween),
+      CurrentHandle::Static(h) => h.pause(tween),
     }
   }
 
-  fn resume(&mut self) {
+  fn resume(&mut self, tween: Tween) {
     match self {
       CurrentHandle::None => {}
-      CurrentHandle::Streaming(h) => h.resume(TWEEN),
-      CurrentHandle::Static(h) => h.resume(TWEEN),
+      CurrentHandle::Streaming(h) => h.resume(tween),
+      CurrentHandle::Static(h) => h.resume(tween),
     }
   }
 
@@ -73,11 +111,7 @@ impl CurrentHandle {
     }
   }
 
-  fn set_volume(&mut self, mut volume: f32, is_muted: bool) {
-    if is_muted {
-      volume = -60.0;
-    }
-
+  fn set_volume(&mut self, volume: f32) {
     match self {
       CurrentHandle::None => {}
       CurrentHandle::Streaming(h) => h.set_volume(Decibels::from(volume), TWEEN),
@@ -85,30 +119,1390 @@ impl CurrentHandle {
     }
   }
 
-  fn set_loop(&mut self, should_loop: bool) {
+  fn set_playback_rate(&mut self, rate: f64) {
     match self {
       CurrentHandle::None => {}
-      CurrentHandle::Streaming(h) => {
-        if should_loop {
-          h.set_loop_region(0.0..);
-        } else {
-          h.set_loop_region(None);
+      CurrentHandle::Streaming(h) => h.set_playback_rate(PlaybackRate(rate), TWEEN),
+      CurrentHandle::Static(h) => h.set_playback_rate(PlaybackRate(rate), TWEEN),
+    }
+  }
+
+  /// Fades the sound to silence, leaving it to end on its own.
+  fn fade_out(&mut self, tween: Tween) {
+    match self {
+      CurrentHandle::None => {}
+      CurrentHandle::Streaming(h) => h.set_volume(Decibels::SILENCE, tween),
+      CurrentHandle::Static(h) => h.set_volume(Decibels::SILENCE, tween),
+    }
+  }
+
+  fn is_stopped(&self) -> bool {
+    match self {
+      CurrentHandle::None => true,
+      CurrentHandle::Streaming(h) => h.state() == PlaybackState::Stopped,
+      CurrentHandle::Static(h) => h.state() == PlaybackState::Stopped,
+    }
+  }
+
+  fn set_loop_region(&mut self, region: Option<Region>) {
+    match self {
+      CurrentHandle::None => {}
+      CurrentHandle::Streaming(h) => h.set_loop_region(region),
+      CurrentHandle::Static(h) => h.set_loop_region(region),
+    }
+  }
+}
+
+/// Clock that only runs while audio is playing. Sounds are started against it,
+/// so the tick the current track ends on is known and the next track can be
+/// scheduled to start on exactly that tick.
+/// The clock runs in wall time, so track time is divided by the playback rate.
+struct Transport {
+  clock: ClockHandle,
+  current_end: Option<ClockTime>,
+  rate: f64,
+}
+
+impl Transport {
+  /// Start time for a track that should begin shortly, recording its end tick.
+  fn start_track(&mut self, duration: f64) -> StartTime {
+    self.clock.start();
+
+    let start = self.clock.time() + seconds_to_ticks(TRANSPORT_START_LEAD);
+    self.current_end = Some(start + self.track_ticks(duration));
+
+    return StartTime::ClockTime(start);
+  }
+
+  /// Re-derives the end tick from the playback position, e.g. after a seek.
+  /// Only accurate to the last rendered block, unlike `start_track`.
+  fn anchor(&mut self, remaining: f64) {
+    self.current_end = Some(self.clock.time() + self.track_ticks(remaining.max(0.0)));
+  }
+
+  /// Ticks it takes to play `seconds` of track time at the current rate.
+  fn track_ticks(&self, seconds: f64) -> u64 {
+    return seconds_to_ticks(seconds / self.rate);
+  }
+
+  fn pause(&mut self) {
+    self.clock.pause();
+  }
+
+  fn resume(&mut self) {
+    self.clock.start();
+  }
+}
+
+/// Next track as opened by the loader thread, off the audio thread.
+struct OpenedTrack {
+  data: StreamingSoundData<FromFileError>,
+  replay_gain: ReplayGain,
+  album_key: String,
+}
+
+impl OpenedTrack {
+  fn open(path: &str) -> Result<Self> {
+    let data = load_streaming_data(path.to_string())?;
+    let replay_gain = read_replay_gain(path);
+    let album_key = read_album_key(path);
+
+    return Ok(Self {
+      data,
+      replay_gain,
+      album_key,
+    });
+  }
+}
+
+/// Track waiting in the "next" slot. `data` holds the decoder until the track
+/// is scheduled on the transport, after which `handle` owns the sound and
+/// `start` is the tick it takes over on. Neither is set while the loader
+/// thread opens the file.
+struct NextTrack {
+  path: String,
+  duration: f64,
+  data: Option<StreamingSoundData<FromFileError>>,
+  handle: CurrentHandle,
+  start: Option<ClockTime>,
+  crossfade: f64,
+  replay_gain: ReplayGain,
+  // `None` until the loader has read the tags
+  album_key: Option<String>,
+}
+
+impl NextTrack {
+  /// Empty slot for `path`, filled in once the next track loader has opened it.
+  fn pending(path: String) -> Self {
+    return Self {
+      path,
+      duration: 0.0,
+      data: None,
+      handle: CurrentHandle::None,
+      start: None,
+      crossfade: 0.0,
+      replay_gain: ReplayGain::default(),
+      album_key: None,
+    };
+  }
+
+  fn is_scheduled(&self) -> bool {
+    !matches!(self.handle, CurrentHandle::None)
+  }
+
+  /// Not on the transport and waiting on the next track loader for data.
+  fn is_loading(&self) -> bool {
+    return !self.is_scheduled() && self.data.is_none();
+  }
+
+  /// Starts decoding the track ahead of time so it begins on the tick the
+  /// current track ends on, or overlaps it by the crossfade length.
+  fn schedule(
+    &mut self,
+    track: &mut TrackHandle,
+    transport: &Transport,
+    current: &mut CurrentHandle,
+    state: &StreamStatus,
+  ) -> Result<()> {
+    if self.is_scheduled() || !state.is_playing || state.is_looping {
+      return Ok(());
+    }
+    let Some(end) = transport.current_end else {
+      return Ok(());
+    };
+    let Some(data) = self.data.take() else {
+      return Ok(());
+    };
+
+    // never longer than either track, and never reaching back before now.
+    // the crossfade is in wall time, track durations are not
+    let crossfade = state
+      .crossfade
+      .duration
+      .min(state.duration / transport.rate)
+      .min(self.duration / transport.rate)
+      .max(0.0);
+    let now = transport.clock.time();
+    let start = ClockTime {
+      ticks: end
+        .ticks
+        .saturating_sub(seconds_to_ticks(crossfade))
+        .max(now.ticks),
+      ..end
+    };
+
+    let volume = output_volume(state, state.replay_gain.gain_for(&self.replay_gain));
+    let mut data = data
+      .start_time(StartTime::ClockTime(start))
+      .volume(volume)
+      .playback_rate(PlaybackRate(transport.rate));
+    if crossfade > 0.0 {
+      let tween = crossfade_tween(&state.crossfade, crossfade, StartTime::Immediate);
+      data = data.fade_in_tween(Some(tween));
+      current.fade_out(crossfade_tween(
+        &state.crossfade,
+        crossfade,
+        StartTime::ClockTime(start),
+      ));
+    }
+
+    let handle = track
+      .play(data)
+      .map_err(|_| Error::Audio("failed to schedule next track".to_string()))?;
+    self.handle = CurrentHandle::Streaming(handle);
+    self.start = Some(start);
+    self.crossfade = crossfade;
+
+    return Ok(());
+  }
+
+  /// Drops the scheduled sound, for when the current track's end tick moves
+  /// (seek, new track, looping), and asks the next track loader for fresh data.
+  /// Any fade-out queued on the current track is cancelled.
+  fn unschedule(
+    &mut self,
+    current: &mut CurrentHandle,
+    state: &StreamStatus,
+    next_loader_tx: &std::sync::mpsc::Sender<String>,
+  ) -> Result<()> {
+    if !self.is_scheduled() {
+      return Ok(());
+    }
+
+    self.cancel(current, state);
+    request_next_load(next_loader_tx, &self.path)?;
+
+    return Ok(());
+  }
+
+  /// Stops the scheduled sound and cancels the fade-out queued on the current track.
+  fn cancel(&mut self, current: &mut CurrentHandle, state: &StreamStatus) {
+    if self.crossfade > 0.0 {
+      current.set_volume(output_volume(state, state.applied_gain));
+    }
+
+    self.stop();
+  }
+
+  fn stop(&mut self) {
+    self.handle.stop(TWEEN);
+    self.handle = CurrentHandle::None;
+    self.start = None;
+    self.crossfade = 0.0;
+  }
+}
+
+/// Everything the audio thread owns. Commands and ticks are applied to it one
+/// at a time from the main event loop.
+struct Player {
+  app_handle: AppHandle<tauri::Wry>,
+  audio_manager: AudioManager<DefaultBackend>,
+  // every sound plays on this track so it runs through the eq chain
+  equalizer: Equalizer,
+  transport: Transport,
+  audio_handle: CurrentHandle,
+  // outgoing track while a crossfade is in progress
+  fading_out: CurrentHandle,
+  next_track: Option<NextTrack>,
+  // ReplayGain tags of the current track
+  replay_gain: ReplayGain,
+  // `get_album_key` of the current track, read once when it is loaded
+  album_key: String,
+  pending_static_data: Option<StaticSoundData>,
+  static_sound_id: i32,
+  loader_tx: std::sync::mpsc::Sender<(i32, String, usize)>,
+  // writes to disk that the audio thread shouldn't wait on
+  disk_tx: std::sync::mpsc::Sender<DiskJob>,
+  // opens the next track, and reopens it after it was taken off the transport
+  next_loader_tx: std::sync::mpsc::Sender<String>,
+  last_position_emit: Instant,
+  last_device_check: Instant,
+  // when a `Minutes` sleep timer runs out
+  sleep_deadline: Option<Instant>,
+  // a sleep fade-out is scheduled or running on the current track
+  sleep_fading: bool,
+  // seek target waiting for the seek fade-out to finish
+  pending_seek: Option<(f64, Instant)>,
+  // `is_looping` from before the a/b region turned it on
+  looping_before_region: Option<bool>,
+  resume_store: ResumeStore,
+  history: History,
+  // listen of the current track, recorded once it ends or is left
+  listen: Option<ActiveListen>,
+  queue: PlayQueue,
+  // the queue changed since it was last published
+  queue_changed: bool,
+  state: StreamStatus,
+}
+
+impl Player {
+  fn handle_action(&mut self, action: StreamAction) -> Result<()> {
+    // anything but another seek lands after the pending one
+    if !matches!(action, StreamAction::Seek(_)) {
+      self.finish_pending_seek()?;
+    }
+
+    match action {
+      StreamAction::Play(path) => {
+        self.queue_mut().play(path.clone());
+        self.play(path)?;
+      }
+      StreamAction::PlayResumed(path) => {
+        self.queue_mut().play(path.clone());
+        self.play(path.clone())?;
+
+        if let Some(position) = self.resume_store.load(&path) {
+          self.seek(position.min(self.state.duration), false)?;
+        }
+      }
+      StreamAction::Enqueue(path) => {
+        self.queue_mut().insert_next(vec![path]);
+        self.sync_next();
+      }
+      StreamAction::QueueAppend(paths) => {
+        self.queue_mut().append(paths);
+        self.sync_next();
+      }
+      StreamAction::QueueInsertNext(paths) => {
+        self.queue_mut().insert_next(paths);
+        self.sync_next();
+      }
+      StreamAction::QueueRemove(index) => {
+        self.queue_mut().remove(index as usize);
+        self.sync_next();
+      }
+      StreamAction::QueueMove { from, to } => {
+        self.queue_mut().move_track(from as usize, to as usize);
+        self.sync_next();
+      }
+      StreamAction::QueueClear => {
+        self.queue_mut().clear();
+        self.sync_next();
+      }
+      StreamAction::QueueShuffle(seed) => {
+        self.queue_mut().shuffle(seed);
+        self.sync_next();
+      }
+      StreamAction::QueuePlay(index) => {
+        if let Some(path) = self.queue_mut().jump(index as usize) {
+          self.play(path)?;
+        }
+      }
+      StreamAction::SetRepeat(mode) => {
+        self.queue_mut().repeat = mode;
+        self.sync_next();
+      }
+      StreamAction::Next => {
+        if let Some(path) = self.queue_mut().skip() {
+          self.play(path)?;
+        }
+      }
+      StreamAction::Previous => {
+        if let Some(path) = self.queue_mut().previous() {
+          self.play(path)?;
+        }
+      }
+      StreamAction::SetLoop(should_loop) => {
+        self.state.is_looping = should_loop;
+        // an explicit choice wins over whatever the region replaced
+        self.looping_before_region = None;
+
+        self.audio_handle.set_loop_region(loop_region(&self.state));
+
+        // a looping track never ends, so nothing may be waiting on its end tick
+        self.reschedule_next();
+      }
+      StreamAction::SetLoopRegion { start, end } => {
+        let start = start.max(0.0);
+        let end = end.min(self.state.duration);
+        // not worth stopping playback over
+        if end <= start {
+          self.report_error(Error::Audio("loop region is empty".to_string()));
+          return Ok(());
+        }
+
+        self.state.loop_region = Some(LoopRegion { start, end });
+        self
+          .looping_before_region
+          .get_or_insert(self.state.is_looping);
+        self.state.is_looping = true;
+
+        self.audio_handle.set_loop_region(loop_region(&self.state));
+
+        // kira only wraps when crossing the end, so jump in from outside
+        let position = self.audio_handle.position();
+        if position < start || position >= end {
+          self.audio_handle.seek_to(start);
+          self.state.position = start;
+        }
+
+        self.reschedule_next();
+      }
+      StreamAction::ClearLoopRegion => {
+        self.clear_loop_region();
+
+        self.reschedule_next();
+      }
+      StreamAction::SetPositionInterval(interval) => {
+        self.state.position_interval = interval;
+      }
+      StreamAction::SetCrossfade(settings) => {
+        self.state.crossfade = settings;
+
+        self.reschedule_next();
+      }
+      StreamAction::SetReplayGain(settings) => {
+        self.state.replay_gain = settings;
+        self.state.applied_gain = settings.gain_for(&self.replay_gain);
+
+        self.apply_volume();
+        // picks up the next track's gain as well
+        self.reschedule_next();
+      }
+      StreamAction::SetEqualizer(settings) => {
+        self.state.equalizer = sanitize_settings(settings);
+        self.equalizer.apply(&self.state.equalizer);
+      }
+      StreamAction::SaveEqPreset(name) => {
+        let settings = self.state.equalizer.clone();
+        let presets = &mut self.state.eq_presets;
+
+        match presets.iter_mut().find(|preset| preset.name == name) {
+          Some(preset) => preset.settings = settings,
+          None => presets.push(EqPreset { name, settings }),
+        }
+      }
+      StreamAction::LoadEqPreset(name) => {
+        let preset = self
+          .state
+          .eq_presets
+          .iter()
+          .find(|preset| preset.name == name);
+
+        if let Some(preset) = preset {
+          self.state.equalizer = sanitize_settings(preset.settings.clone());
+          self.equalizer.apply(&self.state.equalizer);
+        }
+      }
+      StreamAction::DeleteEqPreset(name) => {
+        self.state.eq_presets.retain(|preset| preset.name != name);
+      }
+      StreamAction::SetFades(fades) => {
+        self.state.fades = TransportFades {
+          pause: fades.pause.max(0.0),
+          resume: fades.resume.max(0.0),
+          stop: fades.stop.max(0.0),
+          seek: fades.seek.max(0.0),
+        };
+      }
+      StreamAction::SetStaticMemoryBudget(budget) => {
+        self.state.static_memory_budget = budget;
+
+        // drop or pick up the decoded copy to match the new budget
+        if let Some(path) = self.state.path.clone() {
+          self.load_static(path)?;
         }
       }
-      CurrentHandle::Static(h) => {
-        if should_loop {
-          h.set_loop_region(0.0..);
+      StreamAction::SetSource(source) => {
+        self.state.source = source;
+      }
+      StreamAction::SetPlayCount(play_count) => {
+        self.state.play_count = play_count;
+      }
+      StreamAction::SetResume(resume) => {
+        self.state.resume = ResumeSettings {
+          enabled: resume.enabled,
+          min_duration: resume.min_duration.max(0.0),
+        };
+      }
+      StreamAction::Pause => {
+        let tween = fade_tween(self.state.fades.pause);
+        self.audio_handle.pause(tween);
+        self.fading_out.pause(tween);
+        self.transport.pause();
+
+        self.state.is_playing = false;
+        self.state.position = self.audio_handle.position();
+
+        self.save_resume_position();
+      }
+      StreamAction::Resume => {
+        let tween = fade_tween(self.state.fades.resume);
+        self.audio_handle.resume(tween);
+        self.fading_out.resume(tween);
+
+        // tracks restored from a previous session were never started on the
+        // transport, and a pause fade kept the sound going after the clock stopped
+        if self.transport.current_end.is_none() || self.state.fades.pause > 0.0 {
+          self
+            .transport
+            .anchor(self.state.duration - self.audio_handle.position());
+        }
+        self.transport.resume();
+
+        self.state.is_playing = true;
+
+        self.reschedule_next();
+      }
+      StreamAction::Seek(to) => {
+        let fade = self.state.fades.seek;
+
+        if let Some((target, _)) = self.pending_seek.as_mut() {
+          *target = to;
+        } else if fade > 0.0 && self.state.is_playing {
+          // the jump happens from `tick` once the fade-out is done
+          self.audio_handle.pause(fade_tween(fade));
+          self.pending_seek = Some((to, Instant::now() + Duration::from_secs_f64(fade)));
         } else {
-          h.set_loop_region(None);
+          self.seek(to, false)?;
+        }
+
+        self.state.position = to;
+      }
+      StreamAction::SetPlaybackRate(rate) => {
+        let rate = rate.clamp(MIN_PLAYBACK_RATE, MAX_PLAYBACK_RATE);
+
+        self.audio_handle.set_playback_rate(rate);
+        self.fading_out.set_playback_rate(rate);
+        self
+          .equalizer
+          .set_playback_rate(rate, self.state.preserve_pitch);
+
+        self.state.playback_rate = rate;
+        self.state.position = self.audio_handle.position();
+
+        // the rest of the track now takes a different amount of wall time
+        self.transport.rate = rate;
+        if self.transport.current_end.is_some() {
+          self
+            .transport
+            .anchor(self.state.duration - self.state.position);
+        }
+        self.reschedule_next();
+      }
+      StreamAction::SetPreservePitch(preserve_pitch) => {
+        self.state.preserve_pitch = preserve_pitch;
+
+        self
+          .equalizer
+          .set_playback_rate(self.state.playback_rate, preserve_pitch);
+      }
+      StreamAction::SetOutputDevice(device) => {
+        let previous = std::mem::replace(&mut self.state.output.device, device);
+
+        if let Err(e) = self.reopen_output() {
+          self.state.output.device = previous;
+          self.report_error(e);
+        }
+      }
+      StreamAction::SetBufferSize(buffer_size) => {
+        let previous = std::mem::replace(&mut self.state.output.buffer_size, buffer_size);
+
+        if let Err(e) = self.reopen_output() {
+          self.state.output.buffer_size = previous;
+          self.report_error(e);
+        }
+      }
+      StreamAction::SetSampleRate(sample_rate) => {
+        let previous = std::mem::replace(&mut self.state.output.sample_rate, sample_rate);
+
+        // opening falls back to the default rate, so an unsupported one has
+        // to be caught here
+        if let Err(e) = check_sample_rate(&self.state.output).and_then(|_| self.reopen_output()) {
+          self.state.output.sample_rate = previous;
+          self.report_error(e);
+        }
+      }
+      StreamAction::SetSleepTimer(timer) => {
+        self.clear_sleep_timer();
+        self.set_sleep_timer(timer);
+
+        self.reschedule_next();
+      }
+      StreamAction::CancelSleepTimer => {
+        self.clear_sleep_timer();
+
+        self.reschedule_next();
+      }
+      StreamAction::SetVolume(volume) => {
+        self.state.volume = volume;
+
+        self.apply_volume();
+      }
+      StreamAction::ToggleMute => {
+        self.state.is_muted = !self.state.is_muted;
+
+        self.apply_volume();
+      }
+      StreamAction::Reset => {
+        self.save_resume_position();
+        self.finish_listen(false);
+        self.audio_handle.stop(fade_tween(self.state.fades.stop));
+        self.stop_crossfade();
+        if let Some(mut next) = self.next_track.take() {
+          next.stop();
+        }
+        self.transport.current_end = None;
+
+        self.replay_gain = ReplayGain::default();
+        self.album_key.clear();
+        self.state.applied_gain = 0.0;
+
+        self.state.path = None;
+        self.state.next_path = None;
+        self.state.duration = 0.0;
+        self.state.position = 0.0;
+        self.state.is_playing = false;
+      }
+    }
+
+    return Ok(());
+  }
+
+  /// Jumps within the current track. With `keep_paused`, a swapped-in static
+  /// handle starts paused like the streaming one it replaces.
+  fn seek(&mut self, to: f64, keep_paused: bool) -> Result<()> {
+    // seeking cuts any crossfade short
+    self.stop_crossfade();
+
+    // the stream seeks accurately on its own, the decoded copy only makes it
+    // instant for tracks within the memory budget
+
+    if let Some(static_data) = self.pending_static_data.as_ref() {
+      match self.audio_handle {
+        CurrentHandle::None => {}
+        CurrentHandle::Streaming(ref mut streaming_sound_handle) => {
+          // stop streaming sound
+          streaming_sound_handle.stop(TWEEN);
+
+          let volume = output_volume(&self.state, self.state.applied_gain);
+
+          // swap to static sound data
+          let mut new_handle: StaticSoundHandle = self
+            .equalizer
+            .track
+            .play(
+              static_data
+                .clone()
+                .volume(volume)
+                .playback_rate(PlaybackRate(self.state.playback_rate)),
+            )
+            .map_err(|_| Error::Audio("failed to play static sound".to_string()))?;
+
+          new_handle.seek_to(to);
+          if !self.state.is_playing || keep_paused {
+            new_handle.pause(TWEEN);
+          }
+
+          // retain looping state
+          new_handle.set_loop_region(loop_region(&self.state));
+
+          // swap to static sound handle
+          self.audio_handle = CurrentHandle::Static(new_handle);
+        }
+        CurrentHandle::Static(ref mut static_sound_handle) => {
+          static_sound_handle.seek_to(to);
+        }
+      };
+    }
+
+    self.audio_handle.seek_to(to);
+    self.transport.anchor(self.state.duration - to);
+
+    if let Some(listen) = self.listen.as_mut() {
+      listen.jump(to);
+    }
+
+    self.state.position = to;
+
+    self.reschedule_next();
+
+    return Ok(());
+  }
+
+  /// Performs a seek that was waiting on its fade-out and fades back in.
+  fn finish_pending_seek(&mut self) -> Result<()> {
+    let Some((to, _)) = self.pending_seek.take() else {
+      return Ok(());
+    };
+
+    self.seek(to, true)?;
+    if self.state.is_playing {
+      self.audio_handle.resume(fade_tween(self.state.fades.seek));
+    }
+
+    return Ok(());
+  }
+
+  /// Cold-starts `path` as the current track. The queue is expected to already
+  /// point at it.
+  fn play(&mut self, path: String) -> Result<()> {
+    self.save_resume_position();
+    self.finish_listen(false);
+
+    // stop previous track
+    self.audio_handle.stop(fade_tween(self.state.fades.stop));
+    self.stop_crossfade();
+
+    // trigger static sound data loader
+    self.load_static(path.clone())?;
+
+    // check if file exists/is valid
+    if !std::path::Path::new(&path).is_file() {
+      return Err(Error::Audio("File does not exist".to_string()));
+    }
+
+    let new_sound_data = load_streaming_data(path.clone()).map_err(|_| {
+      Error::Audio("Failed to load file. Please ensure it is well-formed and readable".to_string())
+    })?;
+    let duration = new_sound_data.duration().as_secs_f64();
+    let start_time = self.transport.start_track(duration);
+    let new_handle = self
+      .equalizer
+      .track
+      .play(
+        new_sound_data
+          .start_time(start_time)
+          .playback_rate(PlaybackRate(self.state.playback_rate)),
+      )
+      .map_err(|_| Error::Audio("failed to play streaming sound".to_string()))?;
+
+    // set to streaming sound handle for instant playback
+    self.audio_handle = CurrentHandle::Streaming(new_handle);
+
+    self.replay_gain = read_replay_gain(&path);
+    self.album_key = read_album_key(&path);
+    self.state.applied_gain = self.state.replay_gain.gain_for(&self.replay_gain);
+
+    // a/b regions belong to the track they were set on
+    self.clear_loop_region();
+    self
+      .audio_handle
+      .set_volume(output_volume(&self.state, self.state.applied_gain));
+
+    self.listen = Some(ActiveListen::new(
+      path.clone(),
+      duration,
+      0.0,
+      self.state.source.clone(),
+    ));
+
+    self.state.duration = duration;
+    self.state.is_playing = true;
+    self.state.path = Some(path);
+    self.state.position = 0.0;
+
+    // the end tick moved, so whatever is next has to follow it
+    self.reschedule_next();
+    self.sync_next();
+
+    return Ok(());
+  }
+
+  /// Loads `path` paused at `state.position`, outside of the transport. Used
+  /// when there is no audio graph yet to carry playback over from.
+  fn restore_track(&mut self, path: String) -> Result<()> {
+    self.replay_gain = read_replay_gain(&path);
+    self.album_key = read_album_key(&path);
+    self.state.applied_gain = self.state.replay_gain.gain_for(&self.replay_gain);
+
+    let new_sound_data = load_streaming_data(path)
+      .map_err(|e| Error::Audio(format!("failed to create streaming sound data: {}", e)))?;
+    self.state.duration = new_sound_data.duration().as_secs_f64();
+
+    let mut new_handle = self
+      .equalizer
+      .track
+      .play(new_sound_data.with_settings(StreamingSoundSettings {
+        loop_region: loop_region(&self.state),
+        volume: Value::from(Decibels::from(output_volume(
+          &self.state,
+          self.state.applied_gain,
+        ))),
+        start_position: kira::sound::PlaybackPosition::Seconds(self.state.position),
+        playback_rate: Value::from(PlaybackRate(self.state.playback_rate)),
+        ..Default::default()
+      }))
+      .map_err(|_| Error::Audio("failed to play sound via stream".to_string()))?;
+    new_handle.pause(TWEEN);
+    self.audio_handle = CurrentHandle::Streaming(new_handle);
+    self.transport.current_end = None;
+
+    self.sync_next();
+
+    return Ok(());
+  }
+
+  /// Rebuilds the audio manager for the current output settings and carries
+  /// the current track over at its position. Nothing changes if the new
+  /// manager can't be opened.
+  fn reopen_output(&mut self) -> Result<()> {
+    let (mut audio_manager, is_fallback) = open_audio_manager(&self.state.output)?;
+    let (transport, equalizer) = build_graph(&mut audio_manager, &self.state)?;
+
+    let position = self.audio_handle.position();
+    let was_playing = self.state.is_playing;
+
+    // handles can't move between managers, everything is recreated
+    self.audio_handle = CurrentHandle::None;
+    self.fading_out = CurrentHandle::None;
+    self.next_track = None;
+    self.state.crossfading_from = None;
+    self.state.next_path = None;
+
+    self.audio_manager = audio_manager;
+    self.transport = transport;
+    self.equalizer = equalizer;
+    self.state.output_fallback = is_fallback;
+
+    let Some(path) = self.state.path.clone() else {
+      return Ok(());
+    };
+
+    self.state.position = position;
+    self.state.is_playing = false;
+
+    let res = self.restore_track(path).and_then(|_| {
+      if !was_playing {
+        return Ok(());
+      }
+
+      return self.handle_action(StreamAction::Resume);
+    });
+    if let Err(e) = res {
+      handle_action_error(&self.app_handle, &mut self.state, e);
+    }
+
+    return Ok(());
+  }
+
+  /// Switches away from a selected device that disappeared, and back once it
+  /// returns. An unselected (default) device is followed by kira itself.
+  fn check_output_device(&mut self) {
+    if self.last_device_check.elapsed() < DEVICE_CHECK_INTERVAL {
+      return;
+    }
+    self.last_device_check = Instant::now();
+
+    let Some(device) = self.state.output.device.as_deref() else {
+      return;
+    };
+
+    let is_available = find_output_device(device).is_some();
+    if is_available != self.state.output_fallback {
+      return;
+    }
+
+    if is_available {
+      log::info!("output device {device} is back, switching to it");
+    } else {
+      log::warn!("output device {device} disappeared, falling back to the default device");
+    }
+
+    if let Err(e) = self.reopen_output() {
+      self.report_error(e);
+    }
+    self.emit_state();
+  }
+
+  fn load_static(&mut self, path: String) -> Result<()> {
+    // increment static sound id to load new static sound data
+    // without dealing with race conditions
+    self.static_sound_id += 1;
+    // reset existing static sound data to prepare for new load
+    self.pending_static_data = None;
+
+    let max_bytes = self.state.static_memory_budget as usize * 1024 * 1024;
+
+    self
+      .loader_tx
+      .send((self.static_sound_id, path, max_bytes))
+      .map_err(|_| Error::Audio("failed to send load finished event".to_string()))
+  }
+
+  /// Counts the current listen as a play once it passes the thresholds.
+  fn count_play(&mut self) {
+    let Some(listen) = self.listen.as_mut() else {
+      return;
+    };
+    if !listen.take_play(&self.state.play_count) {
+      return;
+    }
+
+    let path = listen.path().to_string();
+    let history = self.history.clone();
+    let app_handle = self.app_handle.clone();
+    self.write_to_disk(move || {
+      let Some(play_count) = history.count_play(&path) else {
+        return;
+      };
+
+      if let Err(e) = (PlayCounted { path, play_count }).emit(&app_handle) {
+        log::error!("failed to emit play count: {e}");
+      }
+    });
+  }
+
+  fn finish_listen(&mut self, completed: bool) {
+    if let Some(listen) = self.listen.take() {
+      let history = self.history.clone();
+      self.write_to_disk(move || history.record(listen, completed));
+    }
+  }
+
+  /// Remembers the position of the current track if it is long enough to
+  /// qualify.
+  fn save_resume_position(&self) {
+    let Some(path) = self.state.path.as_ref() else {
+      return;
+    };
+    if !self.state.resume.enabled || self.state.duration < self.state.resume.min_duration {
+      return;
+    }
+
+    let resume_store = self.resume_store.clone();
+    let path = path.clone();
+    let position = self.audio_handle.position();
+    let duration = self.state.duration;
+    self.write_to_disk(move || resume_store.save(&path, position, duration));
+  }
+
+  /// Forgets the resume position of a track that was played to its end.
+  fn clear_resume_position(&self, path: &str) {
+    let resume_store = self.resume_store.clone();
+    let path = path.to_string();
+    self.write_to_disk(move || resume_store.clear(&path));
+  }
+
+  /// Pushes volume, mute and ReplayGain changes to the current and next track.
+  fn apply_volume(&mut self) {
+    self
+      .audio_handle
+      .set_volume(output_volume(&self.state, self.state.applied_gain));
+
+    if let Some(next) = self.next_track.as_mut() {
+      let gain = self.state.replay_gain.gain_for(&next.replay_gain);
+      next.handle.set_volume(output_volume(&self.state, gain));
+    }
+  }
+
+  fn stop_crossfade(&mut self) {
+    self.fading_out.stop(TWEEN);
+    self.fading_out = CurrentHandle::None;
+    self.state.crossfading_from = None;
+  }
+
+  /// Points the next slot at whatever the queue plays after the current track.
+  fn sync_next(&mut self) {
+    let upcoming = self.queue.peek_next().cloned();
+    if self.next_track.as_ref().map(|next| &next.path) == upcoming.as_ref() {
+      return;
+    }
+
+    if let Some(mut next) = self.next_track.take() {
+      next.cancel(&mut self.audio_handle, &self.state);
+    }
+    self.state.next_path = None;
+
+    let Some(path) = upcoming else {
+      return;
+    };
+
+    if let Err(e) = request_next_load(&self.next_loader_tx, &path) {
+      self.report_error(e);
+      return;
+    }
+    self.next_track = Some(NextTrack::pending(path.clone()));
+    self.state.next_path = Some(path);
+  }
+
+  /// Puts the next track on the transport once the loader has opened it.
+  /// Loads for a track that has since left the next slot, or that already got
+  /// data again, are dropped.
+  fn finish_next_load(&mut self, path: String, opened: Result<OpenedTrack>) {
+    let Some(next) = self.next_track.as_mut() else {
+      return;
+    };
+    if next.path != path || !next.is_loading() {
+      return;
+    }
+
+    match opened {
+      Ok(opened) => {
+        next.duration = opened.data.duration().as_secs_f64();
+        next.replay_gain = opened.replay_gain;
+        next.album_key = Some(opened.album_key);
+        next.data = Some(opened.data);
+        self.reschedule_next();
+      }
+      Err(e) => {
+        // nothing left to play after the current track
+        self.next_track = None;
+        self.state.next_path = None;
+        self.report_error(e);
+      }
+    }
+  }
+
+  /// Moves the scheduled next track onto the current end tick, or takes it off
+  /// the transport entirely when it shouldn't play yet.
+  fn reschedule_next(&mut self) {
+    if self.sleeps_after_current() {
+      if let Some(next) = self.next_track.as_mut() {
+        if let Err(e) = next.unschedule(&mut self.audio_handle, &self.state, &self.next_loader_tx) {
+          self.report_error(e);
+        }
+      }
+
+      self.schedule_sleep_fade();
+      return;
+    }
+
+    // the sleep timer was cancelled or no longer ends on this track
+    if self.sleep_fading && self.sleep_deadline.is_none() {
+      self.sleep_fading = false;
+      self
+        .audio_handle
+        .set_volume(output_volume(&self.state, self.state.applied_gain));
+    }
+
+    let Some(next) = self.next_track.as_mut() else {
+      return;
+    };
+
+    if let Err(e) = next.unschedule(&mut self.audio_handle, &self.state, &self.next_loader_tx) {
+      self.report_error(e);
+      return;
+    }
+
+    let res = next.schedule(
+      &mut self.equalizer.track,
+      &self.transport,
+      &mut self.audio_handle,
+      &self.state,
+    );
+    if let Err(e) = res {
+      self.report_error(e);
+    }
+  }
+
+  fn set_sleep_timer(&mut self, timer: SleepTimer) {
+    let mut status = SleepTimerStatus {
+      timer,
+      ends_at: None,
+      tracks_left: None,
+    };
+
+    match timer.mode {
+      SleepTimerMode::Minutes(minutes) => {
+        let duration = Duration::from_secs_f64(minutes.max(0.0) * 60.0);
+        self.sleep_deadline = Some(Instant::now() + duration);
+
+        status.ends_at = (SystemTime::now() + duration)
+          .duration_since(UNIX_EPOCH)
+          .ok()
+          .map(|d| d.as_millis() as f64);
+      }
+      SleepTimerMode::Tracks(count) => status.tracks_left = Some(count.max(1)),
+      SleepTimerMode::EndOfTrack => status.tracks_left = Some(1),
+      SleepTimerMode::EndOfAlbum => {}
+    }
+
+    self.state.sleep_timer = Some(status);
+  }
+
+  /// Drops the sleep timer and undoes any fade-out it started.
+  fn clear_sleep_timer(&mut self) {
+    self.state.sleep_timer = None;
+    self.sleep_deadline = None;
+
+    if self.sleep_fading {
+      self.sleep_fading = false;
+      self.apply_volume();
+    }
+  }
+
+  /// Whether the sleep timer stops playback when the current track ends, in
+  /// which case the next track must not be scheduled.
+  fn sleeps_after_current(&self) -> bool {
+    let Some(timer) = self.state.sleep_timer else {
+      return false;
+    };
+
+    return match timer.timer.mode {
+      // fading out towards the deadline, don't let another track start loud
+      SleepTimerMode::Minutes(_) => self.sleep_fading,
+      SleepTimerMode::Tracks(_) | SleepTimerMode::EndOfTrack => timer.tracks_left == Some(1),
+      SleepTimerMode::EndOfAlbum => {
+        // undecided until the loader has read the next track's tags
+        let next = self
+          .next_track
+          .as_ref()
+          .and_then(|next| next.album_key.as_ref());
+
+        next.is_some_and(|next| *next != self.album_key)
+      }
+    };
+  }
+
+  /// Fades the current track out over its last seconds, on the transport so
+  /// the fade ends exactly with the track.
+  fn schedule_sleep_fade(&mut self) {
+    let Some(timer) = self.state.sleep_timer else {
+      return;
+    };
+    if self.sleep_deadline.is_some() || timer.timer.fade_out <= 0.0 || !self.state.is_playing {
+      return;
+    }
+    let Some(end) = self.transport.current_end else {
+      return;
+    };
+
+    let now = self.transport.clock.time();
+    let start = ClockTime {
+      ticks: end
+        .ticks
+        .saturating_sub(seconds_to_ticks(timer.timer.fade_out))
+        .max(now.ticks),
+      ..end
+    };
+    let duration = end.ticks.saturating_sub(start.ticks) as f64 / TRANSPORT_TICKS_PER_SECOND;
+
+    self.audio_handle.fade_out(Tween {
+      duration: Duration::from_secs_f64(duration),
+      easing: Easing::Linear,
+      start_time: StartTime::ClockTime(start),
+    });
+    self.sleep_fading = true;
+  }
+
+  /// Runs down a `Minutes` sleep timer, fading out before the deadline and
+  /// pausing once it passes.
+  fn tick_sleep_timer(&mut self) {
+    let (Some(deadline), Some(timer)) = (self.sleep_deadline, self.state.sleep_timer) else {
+      return;
+    };
+
+    let now = Instant::now();
+    if now >= deadline {
+      if self.state.is_playing {
+        // already faded out by now
+        self.audio_handle.pause(TWEEN);
+        self.fading_out.pause(TWEEN);
+        self.transport.pause();
+
+        self.state.is_playing = false;
+        self.state.position = self.audio_handle.position();
+      }
+
+      self.clear_sleep_timer();
+      self.reschedule_next();
+      self.emit_state();
+      return;
+    }
+
+    let remaining = deadline - now;
+    if self.sleep_fading || !self.state.is_playing || remaining.as_secs_f64() > timer.timer.fade_out
+    {
+      return;
+    }
+
+    let tween = Tween {
+      duration: remaining,
+      easing: Easing::Linear,
+      start_time: StartTime::Immediate,
+    };
+    self.audio_handle.fade_out(tween);
+    self.fading_out.fade_out(tween);
+    self.sleep_fading = true;
+
+    self.reschedule_next();
+  }
+
+  /// Counts a finished track against the sleep timer.
+  fn count_sleep_track(&mut self) {
+    let Some(timer) = self.state.sleep_timer.as_mut() else {
+      return;
+    };
+
+    if let Some(tracks_left) = timer.tracks_left.as_mut() {
+      *tracks_left = tracks_left.saturating_sub(1);
+    }
+  }
+
+  /// Drops the a/b region and puts `is_looping` back to what it was before
+  /// the region turned it on.
+  fn clear_loop_region(&mut self) {
+    self.state.loop_region = None;
+    if let Some(was_looping) = self.looping_before_region.take() {
+      self.state.is_looping = was_looping;
+    }
+
+    self.audio_handle.set_loop_region(loop_region(&self.state));
+  }
+
+  fn report_error(&self, error: Error) {
+    emit_playback_error(&self.app_handle, self.state.path.clone(), error);
+  }
+
+  fn queue_mut(&mut self) -> &mut PlayQueue {
+    self.queue_changed = true;
+    return &mut self.queue;
+  }
+
+  /// Sends a changed queue everywhere it is kept: the status, `get_queue`,
+  /// `QueueChanged` and the prefs file.
+  fn publish_queue(&mut self) {
+    if !std::mem::take(&mut self.queue_changed) {
+      return;
+    }
+
+    self.state.queue = self.queue.status();
+
+    let snapshot = Arc::new(self.queue.clone());
+    *QUEUE.lock().unwrap_or_else(|e| e.into_inner()) = Some(snapshot.clone());
+
+    if let Err(e) = QueueChanged(self.queue.clone()).emit(&self.app_handle) {
+      log::error!("failed to emit queue change: {e}");
+    }
+
+    let app_handle = self.app_handle.clone();
+    self.write_to_disk(move || save_queue(&app_handle, &snapshot));
+  }
+
+  fn write_to_disk(&self, job: impl FnOnce() + Send + 'static) {
+    if self.disk_tx.send(Box::new(job)).is_err() {
+      log::error!("disk writer thread is gone");
+    }
+  }
+
+  fn emit_state(&self) {
+    IS_PLAYING.store(self.state.is_playing, Ordering::Relaxed);
+
+    if let Err(e) = StateChanged(self.state.clone()).emit(&self.app_handle) {
+      log::error!("failed to emit state change: {e}");
+    }
+  }
+
+  fn emit_position(&mut self) {
+    let interval = Duration::from_millis(self.state.position_interval as u64);
+    if interval.is_zero() || self.last_position_emit.elapsed() < interval {
+      return;
+    }
+    self.last_position_emit = Instant::now();
+
+    let event = PlaybackPosition {
+      path: self.state.path.clone(),
+      position: self.state.position,
+      duration: self.state.duration,
+    };
+    if let Err(e) = event.emit(&self.app_handle) {
+      log::error!("failed to emit playback position: {e}");
+    }
+  }
+
+  fn emit_track_ended(&self, path: String) {
+    if let Err(e) = (TrackEnded { path }).emit(&self.app_handle) {
+      log::error!("failed to emit track end: {e}");
+    }
+  }
+
+  fn tick(&mut self) {
+    self.check_output_device();
+    self.tick_sleep_timer();
+
+    if self
+      .pending_seek
+      .is_some_and(|(_, due)| Instant::now() >= due)
+    {
+      if let Err(e) = self.finish_pending_seek() {
+        self.report_error(e);
+      }
+    }
+
+    // position and transport are stale until the seek lands
+    if self.pending_seek.is_some() {
+      return;
+    }
+
+    // outgoing track has faded out completely
+    if self.state.crossfading_from.is_some() && self.fading_out.is_stopped() {
+      self.fading_out = CurrentHandle::None;
+      self.state.crossfading_from = None;
+      self.emit_state();
+    }
+
+    if !self.state.is_playing {
+      return;
+    }
+
+    self.state.position = self.audio_handle.position();
+    if let Some(listen) = self.listen.as_mut() {
+      listen.update(self.state.position);
+    }
+    self.count_play();
+
+    // the transport reached the next track's start tick, so it is already
+    // audible and becomes the current track
+    let now = self.transport.clock.time().ticks;
+    let Some(next) = self
+      .next_track
+      .take_if(|next| next.start.is_some_and(|start| now >= start.ticks))
+    else {
+      // the current track ran out while the next one was still being opened,
+      // it starts right away once the load lands
+      if self.next_track.as_ref().is_some_and(NextTrack::is_loading) {
+        self.emit_position();
+        return;
+      }
+
+      // nothing follows and the current track ran out, the queue is finished
+      if self.audio_handle.is_stopped() {
+        self.audio_handle = CurrentHandle::None;
+        self.transport.current_end = None;
+
+        if let Some(path) = self.state.path.take() {
+          // played to the end, nothing left to resume
+          self.clear_resume_position(&path);
+          if let Some(listen) = self.listen.as_mut() {
+            listen.update(self.state.duration);
+          }
+          self.finish_listen(true);
+          self.emit_track_ended(path);
         }
+        self.state.is_playing = false;
+        self.state.duration = 0.0;
+        self.state.position = 0.0;
+
+        // whatever the sleep timer was waiting for, playback has stopped
+        self.clear_sleep_timer();
+
+        self.emit_state();
+        return;
       }
+
+      self.emit_position();
+      return;
+    };
+
+    let previous = std::mem::replace(&mut self.audio_handle, next.handle);
+    if next.crossfade > 0.0 {
+      self.fading_out.stop(TWEEN);
+      self.fading_out = previous;
+      self.state.crossfading_from = self.state.path.clone();
     }
+
+    self.transport.current_end = next
+      .start
+      .map(|start| start + self.transport.track_ticks(next.duration));
+
+    if let Err(e) = self.load_static(next.path.clone()) {
+      self.report_error(e);
+    }
+
+    if let Some(path) = self.state.path.take() {
+      self.clear_resume_position(&path);
+      self.emit_track_ended(path);
+    }
+
+    // the previous track was heard through to where the next one took over
+    if let Some(listen) = self.listen.as_mut() {
+      listen.update(self.state.duration);
+    }
+    self.count_play();
+    self.finish_listen(true);
+    self.listen = Some(ActiveListen::new(
+      next.path.clone(),
+      next.duration,
+      0.0,
+      self.state.source.clone(),
+    ));
+
+    self.replay_gain = next.replay_gain;
+    self.album_key = next.album_key.unwrap_or_default();
+    self.state.applied_gain = self.state.replay_gain.gain_for(&next.replay_gain);
+
+    // the new track hasn't been faded by anything yet
+    self.sleep_fading = false;
+    self.count_sleep_track();
+
+    self.clear_loop_region();
+    self.queue_mut().advance();
+    self.state.path = Some(next.path);
+    self.state.duration = next.duration;
+    self.state.position = 0.0;
+    self.state.next_path = None;
+
+    self.sync_next();
+    self.publish_queue();
+    self.emit_state();
   }
 }
 
 pub fn spawn_audio_thread(
   mut ui_rx: mpsc::Receiver<(StreamAction, oneshot::Sender<StreamStatus>)>,
   initial_state: Option<StreamStatus>,
+  initial_queue: Option<PlayQueue>,
   app_handle: AppHandle<tauri::Wry>,
 ) -> Result<()> {
   let (event_tx, mut event_rx) = mpsc::channel::<InternalEvent>(32);
@@ -126,23 +1520,23 @@ pub fn spawn_audio_thread(
     }
   });
 
-  let mut audio_manager: AudioManager<DefaultBackend> = AudioManager::new(AudioManagerSettings {
-    internal_buffer_size: 256,
-    ..Default::default()
-  })
-  .map_err(|_| Error::Audio("failed to create audio manager".to_string()))?;
+  // tick loop, drives track transitions
+  let tick_tx = event_tx.clone();
+  thread::spawn(move || loop {
+    thread::sleep(TICK_INTERVAL);
 
-  let mut pending_static_data: Option<StaticSoundData> = None;
-  let mut audio_handle: CurrentHandle = CurrentHandle::None;
+    if let Err(mpsc::error::TrySendError::Closed(_)) = tick_tx.try_send(InternalEvent::Tick) {
+      break;
+    }
+  });
 
-  let (loader_tx, loader_rx) = std::sync::mpsc::channel::<(i32, String)>();
-  let mut static_sound_id: i32 = 0;
+  let (loader_tx, loader_rx) = std::sync::mpsc::channel::<(i32, String, usize)>();
   let loader_event_tx = event_tx.clone();
 
   // static loader thread
   thread::spawn(move || {
-    while let Ok((id, path)) = loader_rx.recv() {
-      let sound = StaticSoundData::from_file(&path).ok();
+    while let Ok((id, path, max_bytes)) = loader_rx.recv() {
+      let sound = load_static_data(&path, max_bytes).ok().flatten();
 
       let res = loader_event_tx.try_send(InternalEvent::LoadFinished { id, data: sound });
       if let Err(e) = res {
@@ -151,6 +1545,34 @@ pub fn spawn_audio_thread(
     }
   });
 
+  let (next_loader_tx, next_loader_rx) = std::sync::mpsc::channel::<String>();
+  let next_loader_event_tx = event_tx.clone();
+
+  // next track loader thread, kept apart from the static loader so a long
+  // decode there doesn't hold up the next track
+  thread::spawn(move || {
+    while let Ok(path) = next_loader_rx.recv() {
+      let opened = OpenedTrack::open(&path);
+
+      // blocks rather than drops, the next track waits on this
+      if next_loader_event_tx
+        .blocking_send(InternalEvent::NextOpened { path, opened })
+        .is_err()
+      {
+        break;
+      }
+    }
+  });
+
+  let (disk_tx, disk_rx) = std::sync::mpsc::channel::<DiskJob>();
+
+  // disk writer thread, runs jobs in the order they were sent
+  thread::spawn(move || {
+    while let Ok(job) = disk_rx.recv() {
+      job();
+    }
+  });
+
   let mut state = initial_state.unwrap_or(StreamStatus {
     is_playing: false,
     position: 0.0,
@@ -159,31 +1581,88 @@ pub fn spawn_audio_thread(
     path: None,
     volume: -10.0,
     is_muted: false,
+    next_path: None,
+    crossfade: CrossfadeSettings::default(),
+    crossfading_from: None,
+    queue: QueueStatus::default(),
+    position_interval: 250,
+    replay_gain: ReplayGainSettings::default(),
+    applied_gain: 0.0,
+    equalizer: EqualizerSettings::default(),
+    eq_presets: Vec::new(),
+    playback_rate: 1.0,
+    preserve_pitch: false,
+    loop_region: None,
+    output: OutputSettings::default(),
+    output_fallback: false,
+    sleep_timer: None,
+    fades: TransportFades::default(),
+    static_memory_budget: 128,
+    resume: ResumeSettings::default(),
   });
+  // the next slot is not restored across restarts
+  state.next_path = None;
+  state.crossfading_from = None;
+  // a sleep timer doesn't carry over to the next session
+  state.sleep_timer = None;
+  state.equalizer = sanitize_settings(state.equalizer);
+  // a stale or edited prefs file may not hold a valid queue
+  let mut queue = initial_queue.unwrap_or_default();
+  queue.sanitize();
+  state.queue = queue.status();
+  *QUEUE.lock().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(queue.clone()));
+  state.playback_rate = state
+    .playback_rate
+    .clamp(MIN_PLAYBACK_RATE, MAX_PLAYBACK_RATE);
+
+  let (mut audio_manager, is_fallback) = open_audio_manager(&state.output)?;
+  let (transport, equalizer) = build_graph(&mut audio_manager, &state)?;
+  state.output_fallback = is_fallback;
+
+  let resume_store = ResumeStore::new(&app_handle);
+  let history = History::new(&app_handle);
+
+  let mut player = Player {
+    app_handle,
+    audio_manager,
+    equalizer,
+    transport,
+    audio_handle: CurrentHandle::None,
+    fading_out: CurrentHandle::None,
+    next_track: None,
+    replay_gain: ReplayGain::default(),
+    album_key: String::new(),
+    pending_static_data: None,
+    static_sound_id: 0,
+    loader_tx,
+    next_loader_tx,
+    disk_tx,
+    last_position_emit: Instant::now(),
+    last_device_check: Instant::now(),
+    sleep_deadline: None,
+    sleep_fading: false,
+    pending_seek: None,
+    looping_before_region: None,
+    resume_store,
+    history,
+    queue,
+    queue_changed: false,
+    listen: None,
+    state,
+  };
 
   // load track if initial state has path
-  if let Some(path) = &state.path {
-    if std::path::Path::new(path).is_file() {
-      static_sound_id += 1;
-      pending_static_data = None;
-      let _ = loader_tx.send((static_sound_id, path.to_string()));
-
-      let new_sound_data = load_streaming_data(path.to_string())
-        .map_err(|e| Error::Audio(format!("failed to create streaming sound data: {}", e)))?;
-      let mut new_handle = audio_manager
-        .play(new_sound_data.with_settings(StreamingSoundSettings {
-          loop_region: if state.is_looping {
-            Some(Region::from(0.0..))
-          } else {
-            None
-          },
-          volume: Value::from(Decibels::from(state.volume)),
-          start_position: PlaybackPosition::Seconds(state.position),
-          ..Default::default()
-        }))
-        .map_err(|_| Error::Audio("failed to play sound via stream".to_string()))?;
-      new_handle.pause(TWEEN);
-      audio_handle = CurrentHandle::Streaming(new_handle);
+  if let Some(path) = player.state.path.clone() {
+    if std::path::Path::new(&path).is_file() {
+      let _ = player.load_static(path.clone());
+
+      player.restore_track(path.clone())?;
+      player.listen = Some(ActiveListen::new(
+        path,
+        player.state.duration,
+        player.state.position,
+        player.state.source.clone(),
+      ));
     }
   }
 
@@ -191,182 +1670,22 @@ pub fn spawn_audio_thread(
   while let Some(event) = event_rx.blocking_recv() {
     match event {
       InternalEvent::Command(action, response_tx) => {
-        match action {
-          StreamAction::Play(path) => {
-            // stop previous track
-            audio_handle.stop();
-
-            // increment static sound id to load new static sound data
-            // without dealing with race conditions
-            static_sound_id += 1;
-            // reset existing static sound data to prepare for new load
-            pending_static_data = None;
-
-            // trigger static sound data loader
-            if loader_tx.send((static_sound_id, path.clone())).is_err() {
-              handle_action_error(
-                &app_handle,
-                &mut state,
-                Error::Audio("failed to send load finished event".to_string()),
-              );
-              let _ = response_tx.send(state.clone());
-              continue;
-            }
-
-            // check if file exists/is valid
-            if !std::path::Path::new(&path).is_file() {
-              handle_action_error(
-                &app_handle,
-                &mut state,
-                Error::Audio("File does not exist".to_string()),
-              );
-              let _ = response_tx.send(state.clone());
-              continue;
-            }
-
-            let new_sound_data = match load_streaming_data(path.clone()) {
-              Ok(data) => data,
-              Err(_) => {
-                handle_action_error(
-                  &app_handle,
-                  &mut state,
-                  Error::Audio(
-                    "Failed to load file. Please ensure it is well-formed and readable".to_string(),
-                  ),
-                );
-                let _ = response_tx.send(state.clone());
-                continue;
-              }
-            };
-            let duration = new_sound_data.duration().as_secs_f64();
-            let new_handle = match audio_manager.play(new_sound_data) {
-              Ok(handle) => handle,
-              Err(_) => {
-                handle_action_error(
-                  &app_handle,
-                  &mut state,
-                  Error::Audio("failed to play streaming sound".to_string()),
-                );
-                let _ = response_tx.send(state.clone());
-                continue;
-              }
-            };
-
-            // set to streaming sound handle for instant playback
-            audio_handle = CurrentHandle::Streaming(new_handle);
-
-            audio_handle.set_loop(state.is_looping);
-            audio_handle.set_volume(state.volume, state.is_muted);
-
-            state.duration = duration;
-            state.is_playing = true;
-            state.path = Some(path.clone());
-            state.position = 0.0;
-
-            let _ = response_tx.send(state.clone());
-          }
-          StreamAction::SetLoop(should_loop) => {
-            audio_handle.set_loop(should_loop);
-
-            state.is_looping = should_loop;
-
-            let _ = response_tx.send(state.clone());
-          }
-          StreamAction::Pause => {
-            audio_handle.pause();
-
-            state.is_playing = false;
-            state.position = audio_handle.position();
-
-            let _ = response_tx.send(state.clone());
-          }
-          StreamAction::Resume => {
-            audio_handle.resume();
-
-            state.is_playing = true;
-
-            let _ = response_tx.send(state.clone());
-          }
-          StreamAction::Seek(to) => {
-            if let Some(static_data) = pending_static_data.as_ref() {
-              match audio_handle {
-                CurrentHandle::None => {}
-                CurrentHandle::Streaming(ref mut streaming_sound_handle) => {
-                  // stop streaming sound
-                  streaming_sound_handle.stop(TWEEN);
-
-                  let volume = if state.is_muted { -60.0 } else { state.volume };
-
-                  // swap to static sound data
-                  let mut new_handle: StaticSoundHandle =
-                    match audio_manager.play(static_data.clone().volume(volume)) {
-                      Ok(handle) => handle,
-                      Err(_) => {
-                        handle_action_error(
-                          &app_handle,
-                          &mut state,
-                          Error::Audio("failed to play static sound".to_string()),
-                        );
-                        let _ = response_tx.send(state.clone());
-                        continue;
-                      }
-                    };
-
-                  new_handle.seek_to(to);
-                  if !state.is_playing {
-                    new_handle.pause(TWEEN);
-                  }
-
-                  // retain looping state
-                  if state.is_looping {
-                    new_handle.set_loop_region(0.0..);
-                  }
-
-                  // swap to static sound handle
-                  audio_handle = CurrentHandle::Static(new_handle);
-                }
-                CurrentHandle::Static(ref mut static_sound_handle) => {
-                  static_sound_handle.seek_to(to);
-                }
-              };
-            }
-
-            audio_handle.seek_to(to);
-
-            state.position = to;
-
-            let _ = response_tx.send(state.clone());
-          }
-          StreamAction::SetVolume(volume) => {
-            audio_handle.set_volume(volume, state.is_muted);
-
-            state.volume = volume;
-            let _ = response_tx.send(state.clone());
-          }
-          StreamAction::ToggleMute => {
-            state.is_muted = !state.is_muted;
-
-            audio_handle.set_volume(state.volume, state.is_muted);
-
-            let _ = response_tx.send(state.clone());
-          }
-          StreamAction::Reset => {
-            audio_handle.stop();
-            state.path = None;
-            state.duration = 0.0;
-            state.position = 0.0;
-            state.is_playing = false;
-
-            let _ = response_tx.send(state.clone());
-          }
+        if let Err(e) = player.handle_action(action) {
+          handle_action_error(&player.app_handle, &mut player.state, e);
         }
+        player.publish_queue();
+
+        let _ = response_tx.send(player.state.clone());
+        player.emit_state();
       }
       InternalEvent::LoadFinished { id, data } => {
         // validate static sound id before updating pending static data
-        if id == static_sound_id {
-          pending_static_data = data;
+        if id == player.static_sound_id {
+          player.pending_static_data = data;
         }
       }
+      InternalEvent::NextOpened { path, opened } => player.finish_next_load(path, opened),
+      InternalEvent::Tick => player.tick(),
     }
   }
 
@@ -374,12 +1693,98 @@ pub fn spawn_audio_thread(
   Ok(())
 }
 
+/// Creates the transport clock and the eq track on a fresh audio manager,
+/// set up for the current settings.
+fn build_graph(
+  audio_manager: &mut AudioManager<DefaultBackend>,
+  state: &StreamStatus,
+) -> Result<(Transport, Equalizer)> {
+  let clock = audio_manager
+    .add_clock(ClockSpeed::TicksPerSecond(TRANSPORT_TICKS_PER_SECOND))
+    .map_err(|_| Error::Audio("failed to create transport clock".to_string()))?;
+  let transport = Transport {
+    clock,
+    current_end: None,
+    rate: state.playback_rate,
+  };
+
+  let mut equalizer = Equalizer::new(audio_manager)?;
+  equalizer.apply(&state.equalizer);
+  equalizer.set_playback_rate(state.playback_rate, state.preserve_pitch);
+
+  return Ok((transport, equalizer));
+}
+
+fn fade_tween(seconds: f64) -> Tween {
+  return Tween {
+    duration: Duration::from_secs_f64(seconds.max(0.0)),
+    easing: Easing::Linear,
+    start_time: StartTime::Immediate,
+  };
+}
+
+fn crossfade_tween(settings: &CrossfadeSettings, duration: f64, start_time: StartTime) -> Tween {
+  return Tween {
+    duration: Duration::from_secs_f64(duration),
+    easing: fade_easing(settings.curve),
+    start_time,
+  };
+}
+
+fn fade_easing(curve: FadeCurve) -> Easing {
+  return match curve {
+    FadeCurve::Linear => Easing::Linear,
+    FadeCurve::EaseIn => Easing::InPowi(2),
+    FadeCurve::EaseOut => Easing::OutPowi(2),
+    FadeCurve::EaseInOut => Easing::InOutPowi(2),
+  };
+}
+
+/// Region the current track loops over: the a/b region if one is set, the
+/// whole track otherwise, or nothing when looping is off.
+fn loop_region(state: &StreamStatus) -> Option<Region> {
+  if !state.is_looping {
+    return None;
+  }
+
+  return Some(match state.loop_region {
+    Some(region) => Region::from(region.start..region.end),
+    None => Region::from(0.0..),
+  });
+}
+
+/// Volume in dB a track should play at, given its ReplayGain offset.
+fn output_volume(state: &StreamStatus, gain: f32) -> f32 {
+  if state.is_muted {
+    return -60.0;
+  }
+
+  return state.volume + gain;
+}
+
+/// Untagged or unreadable files simply play without a gain offset.
+fn read_replay_gain(path: &str) -> ReplayGain {
+  return get_replay_gain(path).unwrap_or_else(|e| {
+    log::warn!("failed to read replaygain tags for {path}: {e}");
+    ReplayGain::default()
+  });
+}
+
+/// Tracks without a readable key all count as one album.
+fn read_album_key(path: &str) -> String {
+  return get_album_key(path).unwrap_or_default();
+}
+
+fn seconds_to_ticks(seconds: f64) -> u64 {
+  return (seconds * TRANSPORT_TICKS_PER_SECOND).round() as u64;
+}
+
 fn handle_action_error(
   app_handle: &AppHandle<tauri::Wry>,
   status: &mut StreamStatus,
   error: Error,
 ) {
-  emit_error(app_handle.clone(), error);
+  emit_playback_error(app_handle, status.path.clone(), error);
 
   status.is_playing = false;
   status.path = None;
@@ -387,11 +1792,30 @@ fn handle_action_error(
   status.position = 0.0;
 }
 
+/// Reports an error both as a generic backend error and as a typed playback event.
+fn emit_playback_error(app_handle: &AppHandle<tauri::Wry>, path: Option<String>, error: Error) {
+  let event = PlaybackError {
+    path,
+    message: error.to_string(),
+  };
+  if let Err(e) = event.emit(app_handle) {
+    log::error!("failed to emit playback error: {e}");
+  }
+
+  emit_error(app_handle.clone(), error);
+}
+
+fn request_next_load(next_loader_tx: &std::sync::mpsc::Sender<String>, path: &str) -> Result<()> {
+  return next_loader_tx
+    .send(path.to_string())
+    .map_err(|_| Error::Audio("next track loader thread is gone".to_string()));
+}
+
 fn load_streaming_data(path: String) -> Result<StreamingSoundData<FromFileError>> {
   let (tx, rx) = std::sync::mpsc::channel();
 
   std::thread::spawn(move || {
-    let res = StreamingSoundData::from_file(&path);
+    let res = GaplessDecoder::from_file(&path).map(StreamingSoundData::from_decoder);
 
     let _ = tx.send(res);
   });
@@ -4,7 +4,7 @@ use image::imageops;
 use std::collections::HashMap;
 use std::fs;
 use std::io::Cursor;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{LazyLock, Mutex};
 use tauri::http::StatusCode;
 use tauri::http::{Request, Response};
@@ -89,18 +89,32 @@ pub fn handler(
       return Ok(cover);
     }
 
-    let hash = format!("{:x}", md5::compute(&cover));
+    return get_thumbnail(cover, &cache_dir);
+  }
+}
 
-    if let Ok(cached_cover) = fs::read(cache_dir.join(format!("{}.jpg", &hash.clone()))) {
-      return Ok(cached_cover);
-    }
+/// Writes the thumbnail for `path` to the cache if it has a cover and the
+/// thumbnail isn't cached yet.
+pub fn cache_thumbnail(path: &str, cache_dir: &Path) -> Result<()> {
+  if let Some(cover) = get_cover(path) {
+    get_thumbnail(cover, cache_dir)?;
+  }
 
-    let resized_cover = resize_cover(cover)?;
+  return Ok(());
+}
 
-    fs::write(cache_dir.join(format!("{}.jpg", hash)), &resized_cover).ok();
+fn get_thumbnail(cover: Vec<u8>, cache_dir: &Path) -> Result<Vec<u8>> {
+  let hash = format!("{:x}", md5::compute(&cover));
 
-    return Ok(resized_cover);
+  if let Ok(cached_cover) = fs::read(cache_dir.join(format!("{}.jpg", &hash.clone()))) {
+    return Ok(cached_cover);
   }
+
+  let resized_cover = resize_cover(cover)?;
+
+  fs::write(cache_dir.join(format!("{}.jpg", hash)), &resized_cover).ok();
+
+  return Ok(resized_cover);
 }
 
 fn decode_path(path: &str) -> Result<String> {
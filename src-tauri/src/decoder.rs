@@ -0,0 +1,387 @@
+use crate::error::{Error, Result};
+use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
+use kira::sound::streaming::Decoder;
+use kira::sound::FromFileError;
+use kira::Frame;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::Arc;
+use symphonia::core::{
+  audio::{SampleBuffer, SignalSpec},
+  codecs::{Decoder as CodecDecoder, DecoderOptions, CODEC_TYPE_NULL},
+  errors::Error as SymphoniaError,
+  formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+  io::MediaSourceStream,
+  meta::MetadataOptions,
+  probe::Hint,
+  units::TimeBase,
+};
+
+/// symphonia-backed decoder for kira with gapless trimming enabled, so encoder
+/// delay and padding are dropped before frames ever reach the mixer.
+pub struct GaplessDecoder {
+  format: Box<dyn FormatReader>,
+  decoder: Box<dyn CodecDecoder>,
+  track_id: u32,
+  sample_rate: u32,
+  /// Unit of packet timestamps, which isn't always one frame (MP4 timescales
+  /// often differ from the sample rate).
+  time_base: Option<TimeBase>,
+  num_frames: usize,
+  /// Rest of the packet a seek landed in, handed out before decoding further.
+  leftover: Vec<Frame>,
+}
+
+impl GaplessDecoder {
+  pub fn from_file(path: impl AsRef<Path>) -> std::result::Result<Self, FromFileError> {
+    let path = path.as_ref();
+    let src = std::fs::File::open(path).map_err(FromFileError::IoError)?;
+    let mss = MediaSourceStream::new(Box::new(src), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(OsStr::to_str) {
+      hint.with_extension(ext);
+    }
+
+    let fmt_opts = FormatOptions {
+      enable_gapless: true,
+      ..Default::default()
+    };
+
+    let probed = symphonia::default::get_probe()
+      .format(&hint, mss, &fmt_opts, &MetadataOptions::default())
+      .map_err(FromFileError::SymphoniaError)?;
+    let format = probed.format;
+
+    let track = format
+      .tracks()
+      .iter()
+      .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+      .ok_or(FromFileError::NoDefaultTrack)?;
+
+    let sample_rate = track
+      .codec_params
+      .sample_rate
+      .ok_or(FromFileError::UnknownSampleRate)?;
+    let time_base = track.codec_params.time_base;
+    // with gapless enabled, n_frames already excludes delay and padding
+    let n_frames = track
+      .codec_params
+      .n_frames
+      .ok_or(FromFileError::UnknownDuration)?;
+    let num_frames = ts_to_frame(n_frames, time_base, sample_rate) as usize;
+    let track_id = track.id;
+
+    let decoder = symphonia::default::get_codecs()
+      .make(&track.codec_params, &DecoderOptions::default())
+      .map_err(FromFileError::SymphoniaError)?;
+
+    return Ok(Self {
+      format,
+      decoder,
+      track_id,
+      sample_rate,
+      time_base,
+      num_frames,
+      leftover: Vec::new(),
+    });
+  }
+
+  /// Decodes the next packet, returning the index of its first frame along
+  /// with the frames.
+  fn next_frames(&mut self) -> std::result::Result<(u64, Vec<Frame>), FromFileError> {
+    loop {
+      let packet = self
+        .format
+        .next_packet()
+        .map_err(FromFileError::SymphoniaError)?;
+
+      if packet.track_id() != self.track_id {
+        continue;
+      }
+
+      let decoded = match self.decoder.decode(&packet) {
+        Ok(decoded) => decoded,
+        Err(SymphoniaError::DecodeError(_)) => continue,
+        Err(err) => return Err(FromFileError::SymphoniaError(err)),
+      };
+
+      let spec = *decoded.spec();
+      let mut buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+      buf.copy_interleaved_ref(decoded);
+
+      let frames = match spec.channels.count() {
+        1 => buf
+          .samples()
+          .iter()
+          .map(|&s| Frame::from_mono(s))
+          .collect::<Vec<Frame>>(),
+        2 => buf
+          .samples()
+          .chunks_exact(2)
+          .map(|lr| Frame::new(lr[0], lr[1]))
+          .collect::<Vec<Frame>>(),
+        _ => return Err(FromFileError::UnsupportedChannelConfiguration),
+      };
+
+      // fully trimmed packets (encoder delay) decode to nothing
+      if frames.is_empty() {
+        continue;
+      }
+
+      let start = ts_to_frame(packet.ts(), self.time_base, self.sample_rate);
+      return Ok((start, frames));
+    }
+  }
+}
+
+impl Decoder for GaplessDecoder {
+  type Error = FromFileError;
+
+  fn sample_rate(&self) -> u32 {
+    self.sample_rate
+  }
+
+  fn num_frames(&self) -> usize {
+    self.num_frames
+  }
+
+  fn decode(&mut self) -> std::result::Result<Vec<Frame>, Self::Error> {
+    if !self.leftover.is_empty() {
+      return Ok(std::mem::take(&mut self.leftover));
+    }
+
+    return self.next_frames().map(|(_, frames)| frames);
+  }
+
+  fn seek(&mut self, index: usize) -> std::result::Result<usize, Self::Error> {
+    let seeked_to = self
+      .format
+      .seek(
+        SeekMode::Accurate,
+        SeekTo::TimeStamp {
+          ts: frame_to_ts(index as u64, self.time_base, self.sample_rate),
+          track_id: self.track_id,
+        },
+      )
+      .map_err(FromFileError::SymphoniaError)?;
+
+    self.decoder.reset();
+    self.leftover.clear();
+
+    // the reader lands on the packet at or before the target using the
+    // container's seek index, so decode forward and drop what comes before it
+    let target = index as u64;
+    loop {
+      let (start, mut frames) = match self.next_frames() {
+        Ok(next) => next,
+        // target is past the last packet
+        Err(FromFileError::SymphoniaError(SymphoniaError::IoError(_))) => {
+          let actual = ts_to_frame(seeked_to.actual_ts, self.time_base, self.sample_rate);
+          return Ok(actual as usize);
+        }
+        Err(err) => return Err(err),
+      };
+
+      if start + frames.len() as u64 <= target {
+        continue;
+      }
+
+      let skip = target.saturating_sub(start) as usize;
+      frames.drain(..skip);
+      self.leftover = frames;
+
+      return Ok((start as usize) + skip);
+    }
+  }
+}
+
+/// Converts a timestamp in `time_base` units to a frame index. Tracks without
+/// a time base count in frames.
+fn ts_to_frame(ts: u64, time_base: Option<TimeBase>, sample_rate: u32) -> u64 {
+  let Some(tb) = time_base else {
+    return ts;
+  };
+
+  let frame = ts as u128 * tb.numer as u128 * sample_rate as u128 / tb.denom as u128;
+  return frame as u64;
+}
+
+/// Converts a frame index to a timestamp in `time_base` units, rounding down
+/// so a seek lands at or before the frame.
+fn frame_to_ts(frame: u64, time_base: Option<TimeBase>, sample_rate: u32) -> u64 {
+  let Some(tb) = time_base else {
+    return frame;
+  };
+
+  let ts = frame as u128 * tb.denom as u128 / (tb.numer as u128 * sample_rate as u128);
+  return ts as u64;
+}
+
+/// Fully decodes a file into a static sound, trimmed the same way as the
+/// streaming decoder so positions line up when swapping between the two.
+/// Returns `None` without decoding when the frames would take more than
+/// `max_bytes`.
+pub fn load_static_data(
+  path: impl AsRef<Path>,
+  max_bytes: usize,
+) -> std::result::Result<Option<StaticSoundData>, FromFileError> {
+  let mut decoder = GaplessDecoder::from_file(path)?;
+  if decoder
+    .num_frames
+    .saturating_mul(std::mem::size_of::<Frame>())
+    > max_bytes
+  {
+    return Ok(None);
+  }
+
+  let mut frames: Vec<Frame> = Vec::with_capacity(decoder.num_frames);
+
+  while frames.len() < decoder.num_frames {
+    match decoder.decode() {
+      Ok(chunk) => frames.extend(chunk),
+      Err(FromFileError::SymphoniaError(SymphoniaError::IoError(_))) => break,
+      Err(err) => return Err(err),
+    }
+  }
+  frames.truncate(decoder.num_frames);
+
+  return Ok(Some(StaticSoundData {
+    sample_rate: decoder.sample_rate,
+    frames: Arc::from(frames),
+    settings: StaticSoundSettings::default(),
+    slice: None,
+  }));
+}
+
+/// Decodes the first audio track of a file from start to end, handing each
+/// packet's samples to `on_samples` interleaved as `[L, R, L, R, ...]`.
+pub fn decode_interleaved(
+  path: impl AsRef<Path>,
+  mut on_samples: impl FnMut(SignalSpec, &[f32]) -> Result<()>,
+) -> Result<()> {
+  let path = path.as_ref();
+  let src = std::fs::File::open(path)
+    .map_err(|e| Error::FileSystem(format!("failed to open media: {}", e)))?;
+  let mss = MediaSourceStream::new(Box::new(src), Default::default());
+
+  let mut hint = Hint::new();
+  if let Some(ext) = path.extension().and_then(OsStr::to_str) {
+    hint.with_extension(ext);
+  }
+
+  let fmt_opts = FormatOptions {
+    enable_gapless: true,
+    ..Default::default()
+  };
+
+  let probed = symphonia::default::get_probe()
+    .format(&hint, mss, &fmt_opts, &MetadataOptions::default())
+    .map_err(|_| Error::Audio("failed to probe format".to_string()))?;
+  let mut format = probed.format;
+
+  let track = format
+    .tracks()
+    .iter()
+    .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+    .ok_or(Error::Audio("no supported audio tracks".to_string()))?;
+  let track_id = track.id;
+
+  let mut decoder = symphonia::default::get_codecs()
+    .make(&track.codec_params, &DecoderOptions::default())
+    .map_err(|_| Error::Audio("unsupported codec".to_string()))?;
+
+  let mut sample_buf: Option<SampleBuffer<f32>> = None;
+
+  loop {
+    let packet = match format.next_packet() {
+      Ok(packet) => packet,
+      Err(SymphoniaError::ResetRequired) => {
+        return Err(Error::Audio("reset required".to_string()));
+      }
+      // end of stream
+      Err(SymphoniaError::IoError(_)) => break,
+      Err(err) => {
+        return Err(Error::Audio(format!("failed to get next packet: {}", err)));
+      }
+    };
+
+    while !format.metadata().is_latest() {
+      format.metadata().pop();
+    }
+
+    if packet.track_id() != track_id {
+      continue;
+    }
+
+    match decoder.decode(&packet) {
+      Ok(decoded) => {
+        let spec = *decoded.spec();
+
+        // reuse the buffer unless a packet is larger than any seen before
+        let needed = decoded.capacity() * spec.channels.count();
+        if sample_buf.as_ref().is_none_or(|b| b.capacity() < needed) {
+          sample_buf = Some(SampleBuffer::<f32>::new(decoded.capacity() as u64, spec));
+        }
+
+        if let Some(sample_buf) = sample_buf.as_mut() {
+          sample_buf.copy_interleaved_ref(decoded);
+          on_samples(spec, sample_buf.samples())?;
+        }
+      }
+      Err(SymphoniaError::IoError(_)) | Err(SymphoniaError::DecodeError(_)) => continue,
+      Err(err) => {
+        return Err(Error::Audio(format!("failed to decode packet: {}", err)));
+      }
+    }
+  }
+
+  return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // ALAC at 8 kHz in an MP4 with a media timescale of 1000, so packet
+  // timestamps are milliseconds rather than frames. Frame `i` holds the
+  // sample `i - 4000`.
+  const FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../test/fixtures/alac-timescale-1000.m4a"
+  );
+
+  fn frame_index(frame: Frame) -> i64 {
+    return (frame.left * 32768.0).round() as i64 + 4000;
+  }
+
+  #[test]
+  fn counts_frames_rather_than_timescale_units() {
+    let decoder = GaplessDecoder::from_file(FIXTURE).unwrap();
+
+    assert_eq!(decoder.sample_rate(), 8000);
+    assert_eq!(decoder.num_frames(), 8000);
+  }
+
+  #[test]
+  fn seeks_to_frames_when_timescale_differs_from_sample_rate() {
+    let mut decoder = GaplessDecoder::from_file(FIXTURE).unwrap();
+
+    for target in [4000, 1234, 0, 7999, 801] {
+      assert_eq!(decoder.seek(target).unwrap(), target);
+
+      let frames = decoder.decode().unwrap();
+      assert_eq!(frame_index(frames[0]), target as i64);
+    }
+  }
+
+  #[test]
+  fn decodes_every_frame_in_order() {
+    let data = load_static_data(FIXTURE, usize::MAX).unwrap().unwrap();
+
+    assert_eq!(data.frames.len(), 8000);
+    for (i, frame) in data.frames.iter().enumerate() {
+      assert_eq!(frame_index(*frame), i as i64);
+    }
+  }
+}
@@ -11,6 +11,16 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    album_loudness (album_key) {
+        album_key -> Text,
+        integrated -> Nullable<Double>,
+        loudness_range -> Double,
+        true_peak -> Double,
+        analyzed_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     library_folders (rowid) {
         rowid -> Integer,
@@ -40,6 +50,35 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    lossy_analysis (path) {
+        path -> Text,
+        file_size -> BigInt,
+        modified_at -> BigInt,
+        verdict -> Text,
+        cutoff -> Nullable<Double>,
+        cliff -> Double,
+        confidence -> Double,
+        sample_rate -> Integer,
+        bands -> Text,
+        analyzed_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    play_history (id) {
+        id -> Nullable<Integer>,
+        track_key -> Nullable<Text>,
+        path -> Text,
+        started_at -> BigInt,
+        listened -> Double,
+        duration -> Double,
+        completed -> Bool,
+        source_type -> Nullable<Text>,
+        source_id -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     playlist_tracks (id) {
         id -> Nullable<Integer>,
@@ -60,6 +99,28 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    resume_positions (path) {
+        path -> Text,
+        file_size -> BigInt,
+        modified_at -> BigInt,
+        position -> Double,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    track_loudness (path) {
+        path -> Text,
+        file_size -> BigInt,
+        modified_at -> BigInt,
+        integrated -> Nullable<Double>,
+        loudness_range -> Double,
+        true_peak -> Double,
+        analyzed_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     track_play_count (id_hash) {
         id_hash -> Nullable<Text>,
@@ -75,10 +136,15 @@ diesel::joinable!(playlist_tracks -> playlists (playlist_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
   _sqlx_migrations,
+  album_loudness,
   library_folders,
   library_tracks,
   library_tracks_source,
+  lossy_analysis,
+  play_history,
   playlist_tracks,
   playlists,
+  resume_positions,
+  track_loudness,
   track_play_count,
 );
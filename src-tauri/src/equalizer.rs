@@ -0,0 +1,161 @@
+use crate::error::{Error, Result};
+use crate::meter::MeterTapBuilder;
+use crate::pitch::{PitchShifterBuilder, PitchShifterHandle};
+use crate::playback::{EqBand, EqBandKind, EqualizerSettings};
+use kira::effect::eq_filter::{EqFilterBuilder, EqFilterHandle, EqFilterKind};
+use kira::effect::filter::{FilterBuilder, FilterHandle, FilterMode};
+use kira::effect::panning_control::{PanningControlBuilder, PanningControlHandle};
+use kira::effect::volume_control::{VolumeControlBuilder, VolumeControlHandle};
+use kira::track::{TrackBuilder, TrackHandle};
+use kira::{AudioManager, Decibels, DefaultBackend, Easing, Mix, Panning, StartTime, Tween};
+use std::time::Duration;
+
+/// Bands available to the user. kira effects can't be added to a track after
+/// it is built, so every slot exists up front and unused ones stay neutral.
+pub const MAX_EQ_BANDS: usize = 10;
+
+// short enough to feel instant, long enough to avoid zipper noise
+const PARAM_TWEEN: Tween = Tween {
+  duration: Duration::from_millis(30),
+  easing: Easing::Linear,
+  start_time: StartTime::Immediate,
+};
+
+const MIN_FREQUENCY: f64 = 20.0;
+const MAX_FREQUENCY: f64 = 20_000.0;
+const MAX_BAND_GAIN: f32 = 24.0;
+const MAX_PREAMP: f32 = 24.0;
+
+/// One band. Peaking and shelf bands run through the eq filter, low-pass and
+/// high-pass through the state-variable filter; whichever isn't used is
+/// left neutral.
+struct BandSlot {
+  eq: EqFilterHandle,
+  filter: FilterHandle,
+}
+
+impl BandSlot {
+  fn apply(&mut self, band: Option<&EqBand>) {
+    let Some(band) = band else {
+      self.eq.set_gain(Decibels::IDENTITY, PARAM_TWEEN);
+      self.filter.set_mix(Mix::DRY, PARAM_TWEEN);
+      return;
+    };
+
+    let pass_mode = match band.kind {
+      EqBandKind::LowPass => Some(FilterMode::LowPass),
+      EqBandKind::HighPass => Some(FilterMode::HighPass),
+      _ => None,
+    };
+
+    if let Some(mode) = pass_mode {
+      self.eq.set_gain(Decibels::IDENTITY, PARAM_TWEEN);
+
+      self.filter.set_mode(mode);
+      self.filter.set_cutoff(band.frequency, PARAM_TWEEN);
+      self
+        .filter
+        .set_resonance(q_to_resonance(band.q), PARAM_TWEEN);
+      self.filter.set_mix(Mix::WET, PARAM_TWEEN);
+      return;
+    }
+
+    self.filter.set_mix(Mix::DRY, PARAM_TWEEN);
+
+    self.eq.set_kind(match band.kind {
+      EqBandKind::LowShelf => EqFilterKind::LowShelf,
+      EqBandKind::HighShelf => EqFilterKind::HighShelf,
+      _ => EqFilterKind::Bell,
+    });
+    self.eq.set_frequency(band.frequency, PARAM_TWEEN);
+    self.eq.set_q(band.q, PARAM_TWEEN);
+    self.eq.set_gain(Decibels(band.gain), PARAM_TWEEN);
+  }
+}
+
+/// Mixer sub-track every sound is played on: pitch correction, preamp, then
+/// the bands, then stereo balance, then the meter tap.
+pub struct Equalizer {
+  pub track: TrackHandle,
+  pitch: PitchShifterHandle,
+  preamp: VolumeControlHandle,
+  bands: Vec<BandSlot>,
+  balance: PanningControlHandle,
+}
+
+impl Equalizer {
+  pub fn new(audio_manager: &mut AudioManager<DefaultBackend>) -> Result<Self> {
+    let mut builder = TrackBuilder::new();
+
+    let pitch = builder.add_effect(PitchShifterBuilder);
+    let preamp = builder.add_effect(VolumeControlBuilder::new(Decibels::IDENTITY));
+    let bands = (0..MAX_EQ_BANDS)
+      .map(|_| BandSlot {
+        eq: builder.add_effect(EqFilterBuilder::new(
+          EqFilterKind::Bell,
+          1000.0,
+          Decibels::IDENTITY,
+          1.0,
+        )),
+        filter: builder.add_effect(FilterBuilder::new().mix(Mix::DRY)),
+      })
+      .collect();
+    let balance = builder.add_effect(PanningControlBuilder::default());
+    builder.add_effect(MeterTapBuilder);
+
+    let track = audio_manager
+      .add_sub_track(builder)
+      .map_err(|_| Error::Audio("failed to create equalizer track".to_string()))?;
+
+    return Ok(Self {
+      track,
+      pitch,
+      preamp,
+      bands,
+      balance,
+    });
+  }
+
+  pub fn apply(&mut self, settings: &EqualizerSettings) {
+    let preamp = if settings.enabled {
+      settings.preamp
+    } else {
+      0.0
+    };
+    self.preamp.set_volume(Decibels(preamp), PARAM_TWEEN);
+
+    for (i, slot) in self.bands.iter_mut().enumerate() {
+      let band = settings.bands.get(i).filter(|_| settings.enabled);
+      slot.apply(band);
+    }
+
+    self
+      .balance
+      .set_panning(Panning(settings.balance), PARAM_TWEEN);
+  }
+
+  /// Shifts pitch back by the playback rate when `preserve_pitch` is set.
+  pub fn set_playback_rate(&mut self, rate: f64, preserve_pitch: bool) {
+    let ratio = if preserve_pitch { 1.0 / rate } else { 1.0 };
+    self.pitch.set_ratio(ratio);
+  }
+}
+
+/// Clamps settings to what the effect chain can represent.
+pub fn sanitize_settings(mut settings: EqualizerSettings) -> EqualizerSettings {
+  settings.bands.truncate(MAX_EQ_BANDS);
+  for band in settings.bands.iter_mut() {
+    band.frequency = band.frequency.clamp(MIN_FREQUENCY, MAX_FREQUENCY);
+    band.gain = band.gain.clamp(-MAX_BAND_GAIN, MAX_BAND_GAIN);
+    band.q = band.q.clamp(0.1, 20.0);
+  }
+  settings.preamp = settings.preamp.clamp(-MAX_PREAMP, MAX_PREAMP);
+  settings.balance = settings.balance.clamp(-1.0, 1.0);
+
+  return settings;
+}
+
+/// kira's filter damping is `2 - 1.9 * resonance`, and damping is `1 / Q`.
+fn q_to_resonance(q: f64) -> f64 {
+  return ((2.0 - 1.0 / q) / 1.9).clamp(0.0, 1.0);
+}
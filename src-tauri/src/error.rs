@@ -9,12 +9,17 @@ pub enum Error {
   Backend(String),
   #[error("ID3 error: `{0}`")]
   Id3(String),
+  #[error("Tag error: `{0}`")]
+  Tag(String),
   #[error("File system error: `{0}`")]
   FileSystem(String),
   #[error("LastFM error: `{0}`")]
   LastFm(String),
   #[error("Waveform error: `{0}`")]
   Waveform(String),
+  // work given up for a newer request, not a failure to report
+  #[error("Cancelled: `{0}`")]
+  Cancelled(String),
   #[allow(dead_code)]
   #[error("SQL error: `{0}`")]
   Sql(String),
@@ -0,0 +1,346 @@
+use crate::diesel_schema::{play_history, track_play_count};
+use crate::error::{Error, Result};
+use crate::playback::{PlayCountSettings, PlaySource, PlaySourceType};
+use crate::read::{get_track_identity, invalidate_track_identity};
+use crate::DbPool;
+use diesel::dsl::{count_star, max, now, sum};
+use diesel::{
+  ExpressionMethods, Insertable, NullableExpressionMethods, QueryDsl, Queryable, RunQueryDsl,
+  Selectable, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::async_runtime::spawn_blocking;
+use tauri::{AppHandle, Manager};
+
+// listens shorter than this were skipped straight past
+const MIN_LISTENED: f64 = 1.0;
+// larger position jumps between ticks are seeks, not listening
+const MAX_LISTEN_STEP: f64 = 1.0;
+
+#[derive(Serialize, Deserialize, Type, Clone, Debug)]
+pub struct Listen {
+  pub path: String,
+  /// Identity key from title and artist, shared with play counts.
+  pub track_key: Option<String>,
+  /// Unix time in seconds.
+  pub started_at: f64,
+  /// Seconds of the track actually heard, excluding skipped parts.
+  pub listened: f64,
+  pub duration: f64,
+  /// The track played through to its end.
+  pub completed: bool,
+  pub source: Option<PlaySource>,
+}
+
+#[derive(Serialize, Deserialize, Type, Clone, Debug)]
+pub struct RecentTrack {
+  pub path: String,
+  /// Unix time in seconds.
+  pub last_played: f64,
+}
+
+#[derive(Serialize, Deserialize, Type, Clone, Debug)]
+pub struct TrackPlays {
+  pub path: String,
+  pub plays: u32,
+  pub listened: f64,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = play_history)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct ListenRow {
+  track_key: Option<String>,
+  path: String,
+  started_at: i64,
+  listened: f64,
+  duration: f64,
+  completed: bool,
+  source_type: Option<String>,
+  source_id: Option<String>,
+}
+
+impl From<ListenRow> for Listen {
+  fn from(row: ListenRow) -> Self {
+    let source_type = row.source_type.as_deref().and_then(PlaySourceType::parse);
+    let source = source_type
+      .zip(row.source_id)
+      .map(|(source_type, source_id)| PlaySource {
+        source_type,
+        source_id,
+      });
+
+    return Self {
+      path: row.path,
+      track_key: row.track_key,
+      started_at: row.started_at as f64,
+      listened: row.listened,
+      duration: row.duration,
+      completed: row.completed,
+      source,
+    };
+  }
+}
+
+/// Listen in progress on the audio thread.
+pub struct ActiveListen {
+  path: String,
+  started_at: i64,
+  duration: f64,
+  source: Option<PlaySource>,
+  listened: f64,
+  last_position: f64,
+  counted: bool,
+}
+
+impl ActiveListen {
+  pub fn new(path: String, duration: f64, position: f64, source: Option<PlaySource>) -> Self {
+    let started_at = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |duration| duration.as_secs() as i64);
+
+    return Self {
+      path,
+      started_at,
+      duration,
+      source,
+      listened: 0.0,
+      last_position: position,
+      counted: false,
+    };
+  }
+
+  /// Counts the progress since the last update as listened, unless playback
+  /// jumped there.
+  pub fn update(&mut self, position: f64) {
+    let step = position - self.last_position;
+    if step > 0.0 && step <= MAX_LISTEN_STEP {
+      self.listened += step;
+    }
+
+    self.last_position = position;
+  }
+
+  /// Returns `true` once, when the listen first satisfies `settings`.
+  pub fn take_play(&mut self, settings: &PlayCountSettings) -> bool {
+    if self.counted || !settings.counts(self.listened, self.duration) {
+      return false;
+    }
+
+    self.counted = true;
+    return true;
+  }
+
+  pub fn path(&self) -> &str {
+    return &self.path;
+  }
+
+  /// Moves to `position` without counting anything in between.
+  pub fn jump(&mut self, position: f64) {
+    self.last_position = position;
+  }
+}
+
+/// Writes finished listens to `play_history` and plays to `track_play_count`.
+/// Best effort: anything that can't be written is logged and dropped.
+#[derive(Clone)]
+pub struct History {
+  pool: Option<DbPool>,
+}
+
+impl History {
+  pub fn new(app_handle: &AppHandle<tauri::Wry>) -> Self {
+    let pool = app_handle
+      .try_state::<DbPool>()
+      .map(|pool| pool.inner().clone());
+
+    return Self { pool };
+  }
+
+  pub fn record(&self, listen: ActiveListen, completed: bool) {
+    if listen.listened < MIN_LISTENED {
+      return;
+    }
+
+    let Some(pool) = self.pool.as_ref() else {
+      return;
+    };
+    let mut conn = match pool.get() {
+      Ok(conn) => conn,
+      Err(e) => {
+        log::warn!("play history unavailable: {e}");
+        return;
+      }
+    };
+
+    let row = ListenRow {
+      track_key: get_track_identity(&listen.path),
+      path: listen.path,
+      started_at: listen.started_at,
+      listened: listen.listened,
+      duration: listen.duration,
+      completed,
+      source_type: listen
+        .source
+        .as_ref()
+        .map(|source| source.source_type.as_str().to_string()),
+      source_id: listen.source.map(|source| source.source_id),
+    };
+
+    if let Err(e) = diesel::insert_into(play_history::table)
+      .values(&row)
+      .execute(&mut conn)
+    {
+      log::warn!("failed to record listen: {e}");
+    }
+  }
+
+  /// Adds a play to the track's count, shared by every file with the same
+  /// title and artist. Returns the new count.
+  pub fn count_play(&self, path: &str) -> Option<i32> {
+    let key = get_track_identity(path)?;
+    let mut conn = self
+      .pool
+      .as_ref()?
+      .get()
+      .inspect_err(|e| log::warn!("play counts unavailable: {e}"))
+      .ok()?;
+
+    let upsert = diesel::insert_into(track_play_count::table)
+      .values((
+        track_play_count::id_hash.eq(&key),
+        track_play_count::last_updated_from.eq("local"),
+        track_play_count::play_count.eq(1),
+      ))
+      .on_conflict(track_play_count::id_hash)
+      .do_update()
+      .set((
+        track_play_count::play_count.eq(track_play_count::play_count + 1),
+        track_play_count::last_updated.eq(now.nullable()),
+        track_play_count::last_updated_from.eq("local"),
+      ))
+      .execute(&mut conn);
+    if let Err(e) = upsert {
+      log::warn!("failed to update play count: {e}");
+      return None;
+    }
+
+    let count = track_play_count::table
+      .find(&key)
+      .select(track_play_count::play_count)
+      .first::<i32>(&mut conn)
+      .inspect_err(|e| log::warn!("failed to read play count: {e}"))
+      .ok()?;
+
+    invalidate_track_identity(&key);
+
+    return Some(count);
+  }
+}
+
+/// Distinct tracks, most recently played first.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_recently_played(
+  app_handle: AppHandle<tauri::Wry>,
+  limit: u32,
+) -> Result<Vec<RecentTrack>> {
+  return spawn_blocking(move || {
+    let mut conn = app_handle
+      .state::<DbPool>()
+      .get()
+      .map_err(|e| Error::Sql(e.to_string()))?;
+
+    let rows = play_history::table
+      .group_by(play_history::path)
+      .select((play_history::path, max(play_history::started_at)))
+      .order_by(max(play_history::started_at).desc())
+      .limit(limit as i64)
+      .load::<(String, Option<i64>)>(&mut conn)
+      .map_err(|e| Error::Sql(e.to_string()))?;
+
+    return Ok(
+      rows
+        .into_iter()
+        .map(|(path, last_played)| RecentTrack {
+          path,
+          last_played: last_played.unwrap_or(0) as f64,
+        })
+        .collect(),
+    );
+  })
+  .await
+  .map_err(|e| Error::Sql(e.to_string()))?;
+}
+
+/// Tracks with the most listens started between `from` and `to`, in unix
+/// seconds.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_most_played(
+  app_handle: AppHandle<tauri::Wry>,
+  from: f64,
+  to: f64,
+  limit: u32,
+) -> Result<Vec<TrackPlays>> {
+  return spawn_blocking(move || {
+    let mut conn = app_handle
+      .state::<DbPool>()
+      .get()
+      .map_err(|e| Error::Sql(e.to_string()))?;
+
+    let rows = play_history::table
+      .filter(play_history::started_at.between(from as i64, to as i64))
+      .group_by(play_history::path)
+      .select((
+        play_history::path,
+        count_star(),
+        sum(play_history::listened),
+      ))
+      .order_by(count_star().desc())
+      .limit(limit as i64)
+      .load::<(String, i64, Option<f64>)>(&mut conn)
+      .map_err(|e| Error::Sql(e.to_string()))?;
+
+    return Ok(
+      rows
+        .into_iter()
+        .map(|(path, plays, listened)| TrackPlays {
+          path,
+          plays: plays as u32,
+          listened: listened.unwrap_or(0.0),
+        })
+        .collect(),
+    );
+  })
+  .await
+  .map_err(|e| Error::Sql(e.to_string()))?;
+}
+
+/// Every listen of a track, oldest first.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_track_listens(
+  app_handle: AppHandle<tauri::Wry>,
+  path: String,
+) -> Result<Vec<Listen>> {
+  return spawn_blocking(move || {
+    let mut conn = app_handle
+      .state::<DbPool>()
+      .get()
+      .map_err(|e| Error::Sql(e.to_string()))?;
+
+    let rows = play_history::table
+      .filter(play_history::path.eq(&path))
+      .order_by(play_history::started_at.asc())
+      .select(ListenRow::as_select())
+      .load(&mut conn)
+      .map_err(|e| Error::Sql(e.to_string()))?;
+
+    return Ok(rows.into_iter().map(Listen::from).collect());
+  })
+  .await
+  .map_err(|e| Error::Sql(e.to_string()))?;
+}
@@ -1,40 +1,41 @@
 use crate::error::{Error, Result};
+use crate::read::ReplayGain;
+use id3::frame::ExtendedText;
 use id3::{Tag, TagLike};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use std::borrow::Cow;
 use std::convert::Into;
+use std::path::Path;
 use tauri::async_runtime::spawn_blocking;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
 pub enum TagTypeArg {
-  // Ape,
-  // #[serde(rename = "id3v1")]
-  // Id3v1,
+  Ape,
+  #[serde(rename = "id3v1")]
+  Id3v1,
   #[serde(rename = "id3v2.2")]
   Id3v22,
   #[serde(rename = "id3v2.3")]
   Id3v23,
   #[serde(rename = "id3v2.4")]
   Id3v24,
-  // Mp4Ilst,
-  // VorbisComments,
-  // RiffInfo,
-  // AiffText,
+  Mp4Ilst,
+  VorbisComments,
+  RiffInfo,
+  AiffText,
 }
 
-impl From<TagTypeArg> for id3::Version {
-  fn from(arg: TagTypeArg) -> Self {
+impl TryFrom<TagTypeArg> for id3::Version {
+  type Error = Error;
+
+  fn try_from(arg: TagTypeArg) -> Result<Self> {
     match arg {
-      // TagTypeArg::Ape => lofty::tag::TagType::Ape,
-      TagTypeArg::Id3v22 => id3::Version::Id3v22,
-      TagTypeArg::Id3v23 => id3::Version::Id3v23,
-      TagTypeArg::Id3v24 => id3::Version::Id3v24,
-      // TagTypeArg::Mp4Ilst => lofty::tag::TagType::Mp4Ilst,
-      // TagTypeArg::VorbisComments => lofty::tag::TagType::VorbisComments,
-      // TagTypeArg::RiffInfo => lofty::tag::TagType::RiffInfo,
-      // TagTypeArg::AiffText => lofty::tag::TagType::AiffText,
+      TagTypeArg::Id3v22 => Ok(id3::Version::Id3v22),
+      TagTypeArg::Id3v23 => Ok(id3::Version::Id3v23),
+      TagTypeArg::Id3v24 => Ok(id3::Version::Id3v24),
+      other => Err(Error::Id3(format!("{:?} is not an ID3v2 tag", other))),
     }
   }
 }
@@ -52,34 +53,104 @@ pub async fn write_id3_frames(
   target_tag: TagTypeArg,
   args: Vec<FrameArgs>,
 ) -> Result<()> {
-  return spawn_blocking(move || {
-    let version = id3::Version::from(target_tag);
-    let mut tag = get_tag(
-      Cow::Borrowed(file_path.as_str()),
-      Cow::Borrowed(&target_tag),
-    )?;
-
-    for arg in args {
-      if arg.value.is_empty() {
-        tag.remove(&arg.frame);
-        continue;
-      }
-
-      tag.set_text(&arg.frame, &arg.value);
+  return spawn_blocking(move || write_frames(&file_path, target_tag, args))
+    .await
+    .map_err(|e| Error::Id3(e.to_string()))?;
+}
+
+/// Sets each frame to its value, removing frames whose value is empty.
+pub fn write_frames(file_path: &str, target_tag: TagTypeArg, args: Vec<FrameArgs>) -> Result<()> {
+  let version = id3::Version::try_from(target_tag)?;
+  let mut tag = get_tag(Cow::Borrowed(file_path), Cow::Borrowed(&target_tag))?;
+
+  for arg in args {
+    if arg.value.is_empty() {
+      tag.remove(&arg.frame);
+      continue;
     }
 
-    tag
-      .write_to_path(&file_path, version)
-      .map_err(|e| Error::Id3(format!("Failed to write ID3 tag: {}", e)))?;
+    tag.set_text(&arg.frame, &arg.value);
+  }
+
+  tag
+    .write_to_path(file_path, version)
+    .map_err(|e| Error::Id3(format!("Failed to write ID3 tag: {}", e)))?;
+
+  return Ok(());
+}
+
+/// Containers the id3 crate writes a tag into without damaging the file.
+pub fn can_write_id3(file_path: &str) -> bool {
+  let ext = Path::new(file_path)
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .map(str::to_ascii_lowercase);
+
+  return matches!(ext.as_deref(), Some("mp3" | "wav" | "aif" | "aiff"));
+}
+
+/// Writes ReplayGain 2.0 `TXXX` frames, replacing existing ones. Values that
+/// are `None` are left untouched. The file keeps its current tag version.
+pub fn write_replay_gain_frames(file_path: &str, replay_gain: &ReplayGain) -> Result<()> {
+  let mut tag = get_tag(Cow::Borrowed(file_path), Cow::Owned(TagTypeArg::Id3v24))?;
+
+  let frames = [
+    (
+      "REPLAYGAIN_TRACK_GAIN",
+      replay_gain.track_gain.map(format_gain),
+    ),
+    (
+      "REPLAYGAIN_TRACK_PEAK",
+      replay_gain.track_peak.map(format_peak),
+    ),
+    (
+      "REPLAYGAIN_ALBUM_GAIN",
+      replay_gain.album_gain.map(format_gain),
+    ),
+    (
+      "REPLAYGAIN_ALBUM_PEAK",
+      replay_gain.album_peak.map(format_peak),
+    ),
+  ];
+
+  for (description, value) in frames {
+    let Some(value) = value else {
+      continue;
+    };
+
+    // writers disagree on the case of the description, so drop every spelling
+    let existing: Vec<String> = tag
+      .extended_texts()
+      .filter(|text| text.description.eq_ignore_ascii_case(description))
+      .map(|text| text.description.clone())
+      .collect();
+    for existing in existing {
+      tag.remove_extended_text(Some(&existing), None);
+    }
+
+    tag.add_frame(ExtendedText {
+      description: description.to_string(),
+      value,
+    });
+  }
+
+  tag
+    .write_to_path(file_path, tag.version())
+    .map_err(|e| Error::Id3(format!("Failed to write ID3 tag: {}", e)))?;
+
+  return Ok(());
+}
+
+fn format_gain(gain: f32) -> String {
+  return format!("{:.2} dB", gain);
+}
 
-    return Ok(());
-  })
-  .await
-  .map_err(|e| Error::Id3(e.to_string()))?;
+fn format_peak(peak: f32) -> String {
+  return format!("{:.6}", peak);
 }
 
 fn get_tag<'a>(file_path: Cow<'a, str>, target_tag: Cow<'a, TagTypeArg>) -> Result<Tag> {
-  let version = id3::Version::from(*target_tag);
+  let version = id3::Version::try_from(*target_tag)?;
 
   let tag = match Tag::read_from_path(file_path.as_ref()) {
     Ok(tag) => tag,
@@ -13,21 +13,34 @@ use tauri::{
 use tauri_plugin_sql::{Migration, MigrationKind};
 use tauri_plugin_store::StoreExt;
 use tauri_plugin_window_state::StateFlags;
-use tauri_specta::collect_commands;
+use tauri_specta::{collect_commands, collect_events};
 use tokio::sync::{mpsc, oneshot};
 
 mod audio;
 mod cover_protocol;
+mod decoder;
 mod diesel_schema;
+mod equalizer;
 mod error;
+mod history;
 mod hooks;
 mod id3;
 mod lastfm;
+mod lossy;
+mod loudness;
+mod meter;
+mod output;
+mod pitch;
 mod playback;
+mod pregen;
 mod read;
+mod resume;
+mod spectrogram;
 mod stronghold;
+mod tags;
 mod utils;
 mod waveform;
+mod waveform_cache;
 
 pub type DbPool = Pool<ConnectionManager<SqliteConnection>>;
 
@@ -170,27 +183,133 @@ pub async fn run() {
     ",
       version: 9,
     },
+    Migration {
+      kind: MigrationKind::Up,
+      description: "create loudness tables",
+      sql: "
+          CREATE TABLE track_loudness (
+            path TEXT PRIMARY KEY NOT NULL,
+            file_size BIGINT NOT NULL,
+            modified_at BIGINT NOT NULL,
+            integrated DOUBLE,
+            loudness_range DOUBLE NOT NULL,
+            true_peak DOUBLE NOT NULL,
+            analyzed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+          );
+
+          CREATE TABLE album_loudness (
+            album_key TEXT PRIMARY KEY NOT NULL,
+            integrated DOUBLE,
+            loudness_range DOUBLE NOT NULL,
+            true_peak DOUBLE NOT NULL,
+            analyzed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+          );
+    ",
+      version: 10,
+    },
+    Migration {
+      kind: MigrationKind::Up,
+      description: "create resume positions table",
+      sql: "
+          CREATE TABLE resume_positions (
+            path TEXT PRIMARY KEY NOT NULL,
+            file_size BIGINT NOT NULL,
+            modified_at BIGINT NOT NULL,
+            position DOUBLE NOT NULL,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+          );
+    ",
+      version: 11,
+    },
+    Migration {
+      kind: MigrationKind::Up,
+      description: "create play history table",
+      sql: "
+          CREATE TABLE play_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            track_key TEXT,
+            path TEXT NOT NULL,
+            started_at BIGINT NOT NULL,
+            listened DOUBLE NOT NULL,
+            duration DOUBLE NOT NULL,
+            completed BOOLEAN NOT NULL,
+            source_type TEXT,
+            source_id TEXT
+          );
+
+          CREATE INDEX play_history_started_at ON play_history (started_at);
+          CREATE INDEX play_history_path ON play_history (path);
+    ",
+      version: 12,
+    },
+    Migration {
+      kind: MigrationKind::Up,
+      description: "create lossy analysis table",
+      sql: "
+          CREATE TABLE lossy_analysis (
+            path TEXT PRIMARY KEY NOT NULL,
+            file_size BIGINT NOT NULL,
+            modified_at BIGINT NOT NULL,
+            verdict TEXT NOT NULL,
+            cutoff DOUBLE,
+            cliff DOUBLE NOT NULL,
+            confidence DOUBLE NOT NULL,
+            sample_rate INTEGER NOT NULL,
+            bands TEXT NOT NULL,
+            analyzed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+          );
+    ",
+      version: 13,
+    },
   ];
 
-  let rpc_builder = tauri_specta::Builder::<tauri::Wry>::new().commands(collect_commands![
-    read::read_folder,
-    read::get_canonical_path,
-    read::get_track_data,
-    read::get_tracks_data,
-    read::get_folder_track_paths,
-    playback::control_playback,
-    waveform::get_waveform,
-    lastfm::open_lastfm_auth,
-    lastfm::complete_lastfm_auth,
-    lastfm::remove_lastfm_account,
-    lastfm::scrobble_track,
-    lastfm::process_offline_scrobbles,
-    lastfm::set_now_playing,
-    lastfm::get_lastfm_auth_status,
-    lastfm::get_lastfm_profile,
-    lastfm::get_lastfm_play_count,
-    id3::write_id3_frames,
-  ]);
+  let rpc_builder = tauri_specta::Builder::<tauri::Wry>::new()
+    .commands(collect_commands![
+      read::read_folder,
+      read::get_canonical_path,
+      read::get_track_data,
+      read::get_tracks_data,
+      read::get_folder_track_paths,
+      playback::control_playback,
+      playback::get_queue,
+      waveform::get_waveform,
+      waveform::get_waveform_range,
+      waveform::purge_waveform_cache,
+      pregen::start_pregen,
+      pregen::stop_pregen,
+      lastfm::open_lastfm_auth,
+      lastfm::complete_lastfm_auth,
+      lastfm::remove_lastfm_account,
+      lastfm::scrobble_track,
+      lastfm::process_offline_scrobbles,
+      lastfm::set_now_playing,
+      lastfm::get_lastfm_auth_status,
+      lastfm::get_lastfm_profile,
+      lastfm::get_lastfm_play_count,
+      id3::write_id3_frames,
+      tags::write_tags,
+      loudness::analyze_loudness,
+      output::list_output_devices,
+      history::get_recently_played,
+      history::get_most_played,
+      history::get_track_listens,
+      meter::subscribe_meter,
+      meter::unsubscribe_meter,
+      spectrogram::get_spectrogram,
+      lossy::analyze_lossy,
+      lossy::analyze_lossy_folder,
+    ])
+    .events(collect_events![
+      playback::PlaybackPosition,
+      playback::TrackEnded,
+      playback::PlayCounted,
+      lossy::LossyProgress,
+      waveform::WaveformProgress,
+      pregen::PregenProgress,
+      playback::PlaybackError,
+      playback::StateChanged,
+      playback::QueueChanged,
+    ]);
 
   #[cfg(debug_assertions)]
   {
@@ -226,7 +345,9 @@ pub async fn run() {
   }
 
   return builder
-    .setup(|app| {
+    .setup(move |app| {
+      rpc_builder.mount_events(app);
+
       let mut win_builder = WebviewWindowBuilder::new(app, "main", WebviewUrl::default())
         .title("swim")
         .inner_size(800.0, 600.0)
@@ -276,9 +397,12 @@ pub async fn run() {
       app.manage(AudioHandle { tx });
 
       let initial_state = get_initial_state(app.app_handle())?;
+      let initial_queue = playback::load_queue(app.app_handle());
       let audio_thread_app_handle = app.app_handle().clone();
       let _ = std::thread::spawn(move || {
-        if let Err(e) = audio::spawn_audio_thread(rx, initial_state, audio_thread_app_handle) {
+        if let Err(e) =
+          audio::spawn_audio_thread(rx, initial_state, initial_queue, audio_thread_app_handle)
+        {
           // log::error!("Failed to spawn audio thread: {e}");
           println!("Failed to spawn audio thread: {e}");
         }
@@ -340,6 +464,7 @@ pub async fn run() {
     .register_asynchronous_uri_scheme_protocol("cover-thumbnail", |ctx, req, responder| {
       cover_protocol::handler(ctx, req, responder, cover_protocol::CoverMode::Thumbnail)
     })
+    .register_asynchronous_uri_scheme_protocol("spectrogram", spectrogram::handler)
     .plugin(tauri_plugin_pinia::init())
     .plugin(tauri_plugin_system_fonts::init())
     .plugin(tauri_plugin_drag::init())
@@ -0,0 +1,386 @@
+use crate::decoder::{decode_interleaved, GaplessDecoder};
+use crate::diesel_schema::lossy_analysis;
+use crate::error::{Error, Result};
+use crate::read::collect_track_paths;
+use crate::spectrogram::{SpectrogramWindow, Stft};
+use crate::utils::FileSignature;
+use crate::DbPool;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::{
+  ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, RunQueryDsl, Selectable,
+  SelectableHelper, SqliteConnection,
+};
+use kira::sound::streaming::Decoder;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use std::path::Path;
+use tauri::async_runtime::spawn_blocking;
+use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+
+// fine enough to place a cutoff within a few Hz at 44.1/48 kHz
+const FFT_SIZE: usize = 8192;
+// windows spread over the track, averaged into one spectrum
+const WINDOW_COUNT: usize = 600;
+// band the reference level is taken from, where every recording has content
+const REFERENCE_BAND: (f64, f64) = (1000.0, 4000.0);
+// encoders low-pass somewhere above this
+const MIN_CUTOFF: f64 = 10_000.0;
+// width compared on either side of a candidate cutoff
+const CLIFF_WIDTH: f64 = 500.0;
+// drop across a cutoff that reads as a lowpass filter rather than a roll-off
+const CLIFF_DB: f64 = 18.0;
+const STRONG_CLIFF_DB: f64 = 40.0;
+// level below the reference that counts as no content
+const CONTENT_FLOOR_DB: f64 = 60.0;
+const BANDS: &[(f64, f64)] = &[
+  (12_000.0, 14_000.0),
+  (14_000.0, 16_000.0),
+  (16_000.0, 18_000.0),
+  (18_000.0, 20_000.0),
+  (20_000.0, 22_000.0),
+  (22_000.0, 24_000.0),
+];
+const LOSSLESS_EXTENSIONS: &[&str] = &["flac", "wav", "aif", "aiff"];
+
+#[derive(Serialize, Deserialize, Type, Clone, Copy, Debug, PartialEq)]
+pub enum LossyVerdict {
+  /// Content reaches the top of the spectrum without a lowpass cliff.
+  Lossless,
+  /// A brick-wall cutoff like the lowpass of a lossy encoder.
+  Lossy,
+  /// Too little high-frequency content to tell, e.g. quiet or dull recordings.
+  Inconclusive,
+}
+
+impl LossyVerdict {
+  fn as_str(&self) -> &'static str {
+    return match self {
+      LossyVerdict::Lossless => "lossless",
+      LossyVerdict::Lossy => "lossy",
+      LossyVerdict::Inconclusive => "inconclusive",
+    };
+  }
+
+  fn parse(value: &str) -> Option<Self> {
+    return match value {
+      "lossless" => Some(LossyVerdict::Lossless),
+      "lossy" => Some(LossyVerdict::Lossy),
+      "inconclusive" => Some(LossyVerdict::Inconclusive),
+      _ => None,
+    };
+  }
+}
+
+#[derive(Serialize, Deserialize, Type, Clone, Copy, Debug)]
+pub struct BandEnergy {
+  pub from: f64,
+  pub to: f64,
+  /// Average level relative to the 1-4 kHz reference, in dB.
+  pub level: f64,
+}
+
+#[derive(Serialize, Deserialize, Type, Clone, Debug)]
+pub struct LossyReport {
+  pub path: String,
+  pub verdict: LossyVerdict,
+  /// Frequency of the lowpass cliff for lossy files, otherwise the highest
+  /// frequency with content. `None` when nothing clears the floor.
+  pub cutoff: Option<f64>,
+  /// Drop across the cutoff in dB. Large drops mean a steep filter.
+  pub cliff: f64,
+  /// How sure the verdict is, from `0` to `1`.
+  pub confidence: f64,
+  pub sample_rate: u32,
+  pub bands: Vec<BandEnergy>,
+}
+
+#[derive(Serialize, Clone, Deserialize, Type, Debug, Event)]
+pub struct LossyProgress {
+  pub folder: String,
+  pub path: String,
+  pub done: u32,
+  pub total: u32,
+}
+
+/// Analyses one lossless file.
+#[tauri::command]
+#[specta::specta]
+pub async fn analyze_lossy(app_handle: AppHandle<tauri::Wry>, path: String) -> Result<LossyReport> {
+  return spawn_blocking(move || {
+    let mut cache = LossyCache::new(&app_handle);
+
+    return analyze_cached(&mut cache, &path);
+  })
+  .await
+  .map_err(|e| Error::Audio(e.to_string()))?;
+}
+
+/// Analyses every lossless file under `folder`, emitting `LossyProgress` after
+/// each one. Files that fail to decode are logged and left out.
+#[tauri::command]
+#[specta::specta]
+pub async fn analyze_lossy_folder(
+  app_handle: AppHandle<tauri::Wry>,
+  folder: String,
+) -> Result<Vec<LossyReport>> {
+  return spawn_blocking(move || {
+    let mut cache = LossyCache::new(&app_handle);
+
+    let paths: Vec<String> = collect_track_paths(&folder, true)?
+      .into_iter()
+      .filter(|path| is_lossless(path))
+      .collect();
+    let total = paths.len() as u32;
+
+    let mut reports = Vec::with_capacity(paths.len());
+    for (i, path) in paths.into_iter().enumerate() {
+      match analyze_cached(&mut cache, &path) {
+        Ok(report) => reports.push(report),
+        Err(e) => log::warn!("failed to analyse {path}: {e}"),
+      }
+
+      let progress = LossyProgress {
+        folder: folder.clone(),
+        path,
+        done: i as u32 + 1,
+        total,
+      };
+      if let Err(e) = progress.emit(&app_handle) {
+        log::error!("failed to emit lossy analysis progress: {e}");
+      }
+    }
+
+    return Ok(reports);
+  })
+  .await
+  .map_err(|e| Error::Audio(e.to_string()))?;
+}
+
+fn is_lossless(path: &str) -> bool {
+  return Path::new(path)
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .is_some_and(|ext| LOSSLESS_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+}
+
+fn analyze_cached(cache: &mut LossyCache, path: &str) -> Result<LossyReport> {
+  if !is_lossless(path) {
+    return Err(Error::Audio(format!("{} is not a lossless file", path)));
+  }
+
+  let signature = FileSignature::read(path)?;
+  if let Some(report) = cache.get(path, &signature) {
+    return Ok(report);
+  }
+
+  let report = analyze(path)?;
+  cache.store(&signature, &report);
+
+  return Ok(report);
+}
+
+fn analyze(path: &str) -> Result<LossyReport> {
+  let num_frames = GaplessDecoder::from_file(path)
+    .map_err(|e| Error::Audio(format!("failed to open {}: {}", path, e)))?
+    .num_frames();
+
+  let mut stft = Stft::new(FFT_SIZE, SpectrogramWindow::Hann, num_frames, WINDOW_COUNT);
+  let mut power = vec![0.0f64; FFT_SIZE / 2];
+  let mut windows = 0;
+  let mut mono = Vec::new();
+  let mut sample_rate = 0;
+
+  decode_interleaved(path, |spec, samples| {
+    sample_rate = spec.rate;
+
+    let channels = spec.channels.count();
+    mono.clear();
+    mono.extend(
+      samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+    );
+
+    stft.push(&mono, |_, bins| {
+      for (total, db) in power.iter_mut().zip(bins) {
+        *total += 10f64.powf(*db as f64 / 10.0);
+      }
+      windows += 1;
+    });
+
+    return Ok(());
+  })?;
+
+  if windows == 0 {
+    return Err(Error::Audio(format!("{} is too short to analyse", path)));
+  }
+
+  let spectrum: Vec<f64> = power
+    .iter()
+    .map(|total| 10.0 * (total / windows as f64).max(1e-20).log10())
+    .collect();
+
+  return Ok(judge(path, &spectrum, sample_rate));
+}
+
+/// Looks for the sharpest drop above `MIN_CUTOFF`. Encoders low-pass with a
+/// cliff of tens of dB within a few hundred Hz, while natural roll-off and
+/// tape are gradual.
+fn judge(path: &str, spectrum: &[f64], sample_rate: u32) -> LossyReport {
+  let nyquist = sample_rate as f64 / 2.0;
+  let bin_width = nyquist / spectrum.len() as f64;
+  let bin = |frequency: f64| ((frequency / bin_width) as usize).min(spectrum.len());
+
+  let mean = |from: usize, to: usize| -> f64 {
+    let bins = &spectrum[from..to.max(from + 1).min(spectrum.len())];
+    return bins.iter().sum::<f64>() / bins.len() as f64;
+  };
+
+  let reference = mean(bin(REFERENCE_BAND.0), bin(REFERENCE_BAND.1));
+  let floor = reference - CONTENT_FLOOR_DB;
+
+  let width = bin(CLIFF_WIDTH).max(1);
+  let mut cliff = 0.0;
+  let mut cliff_at = None;
+  for k in bin(MIN_CUTOFF).max(width)..spectrum.len().saturating_sub(width) {
+    let drop = mean(k - width, k) - mean(k, k + width);
+    if drop > cliff {
+      cliff = drop;
+      cliff_at = Some(k);
+    }
+  }
+
+  let content_edge = spectrum
+    .iter()
+    .rposition(|level| *level > floor)
+    .map(|k| k as f64 * bin_width);
+
+  let bands: Vec<BandEnergy> = BANDS
+    .iter()
+    .filter(|(from, _)| *from < nyquist)
+    .map(|&(from, to)| BandEnergy {
+      from,
+      to: to.min(nyquist),
+      level: mean(bin(from), bin(to.min(nyquist))) - reference,
+    })
+    .collect();
+
+  let (verdict, cutoff, confidence) = match cliff_at {
+    Some(k) if cliff >= CLIFF_DB && mean(k, k + width) < floor + CLIFF_DB => {
+      let strength = (cliff - CLIFF_DB) / (STRONG_CLIFF_DB - CLIFF_DB);
+      (
+        LossyVerdict::Lossy,
+        Some(k as f64 * bin_width),
+        0.5 + 0.5 * strength.clamp(0.0, 1.0),
+      )
+    }
+    _ => match content_edge {
+      // content all the way up, within the last band below nyquist
+      Some(edge) if edge >= nyquist - 2000.0 => {
+        let top = mean(bin(nyquist - 2000.0), spectrum.len());
+        let strength = (top - floor) / CONTENT_FLOOR_DB;
+        (
+          LossyVerdict::Lossless,
+          Some(edge),
+          0.5 + 0.5 * strength.clamp(0.0, 1.0),
+        )
+      }
+      edge => (LossyVerdict::Inconclusive, edge, 0.0),
+    },
+  };
+
+  return LossyReport {
+    path: path.to_string(),
+    verdict,
+    cutoff,
+    cliff,
+    confidence,
+    sample_rate,
+    bands,
+  };
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = lossy_analysis)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct LossyRow {
+  path: String,
+  file_size: i64,
+  modified_at: i64,
+  verdict: String,
+  cutoff: Option<f64>,
+  cliff: f64,
+  confidence: f64,
+  sample_rate: i32,
+  bands: String,
+}
+
+/// Reports cached in SQLite, best effort like the loudness cache.
+struct LossyCache {
+  conn: Option<PooledConnection<ConnectionManager<SqliteConnection>>>,
+}
+
+impl LossyCache {
+  fn new(app_handle: &AppHandle<tauri::Wry>) -> Self {
+    let conn = app_handle
+      .state::<DbPool>()
+      .get()
+      .inspect_err(|e| log::warn!("lossy analysis cache unavailable: {e}"))
+      .ok();
+
+    return Self { conn };
+  }
+
+  fn get(&mut self, path: &str, signature: &FileSignature) -> Option<LossyReport> {
+    let conn = self.conn.as_mut()?;
+
+    let row = lossy_analysis::table
+      .find(path)
+      .filter(lossy_analysis::file_size.eq(signature.size))
+      .filter(lossy_analysis::modified_at.eq(signature.modified_at))
+      .select(LossyRow::as_select())
+      .first(conn)
+      .optional()
+      .inspect_err(|e| log::warn!("failed to read lossy analysis cache: {e}"))
+      .ok()??;
+
+    return Some(LossyReport {
+      path: row.path,
+      verdict: LossyVerdict::parse(&row.verdict)?,
+      cutoff: row.cutoff,
+      cliff: row.cliff,
+      confidence: row.confidence,
+      sample_rate: row.sample_rate as u32,
+      bands: serde_json::from_str(&row.bands).ok()?,
+    });
+  }
+
+  fn store(&mut self, signature: &FileSignature, report: &LossyReport) {
+    let Some(conn) = self.conn.as_mut() else {
+      return;
+    };
+    let Ok(bands) = serde_json::to_string(&report.bands) else {
+      return;
+    };
+
+    let row = LossyRow {
+      path: report.path.clone(),
+      file_size: signature.size,
+      modified_at: signature.modified_at,
+      verdict: report.verdict.as_str().to_string(),
+      cutoff: report.cutoff,
+      cliff: report.cliff,
+      confidence: report.confidence,
+      sample_rate: report.sample_rate as i32,
+      bands,
+    };
+
+    if let Err(e) = diesel::replace_into(lossy_analysis::table)
+      .values(&row)
+      .execute(conn)
+    {
+      log::warn!("failed to write lossy analysis cache: {e}");
+    }
+  }
+}
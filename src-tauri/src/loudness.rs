@@ -0,0 +1,438 @@
+use crate::decoder::decode_interleaved;
+use crate::diesel_schema::{album_loudness, track_loudness};
+use crate::error::{Error, Result};
+use crate::id3::{can_write_id3, write_replay_gain_frames};
+use crate::read::ReplayGain;
+use crate::utils::FileSignature;
+use crate::DbPool;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::{
+  ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, RunQueryDsl, Selectable,
+  SelectableHelper, SqliteConnection,
+};
+use ebur128::{EbuR128, Mode};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::async_runtime::spawn_blocking;
+use tauri::{AppHandle, Manager};
+
+// ReplayGain 2.0 reference level, in LUFS
+const REFERENCE_LOUDNESS: f64 = -18.0;
+
+#[derive(Serialize, Deserialize, Type, Clone, Copy, Debug)]
+pub struct LoudnessStats {
+  /// Integrated loudness in LUFS. `None` for digital silence.
+  pub integrated: Option<f64>,
+  /// Loudness range in LU.
+  pub loudness_range: f64,
+  /// Highest true peak over all channels, as linear amplitude.
+  pub true_peak: f64,
+  /// ReplayGain 2.0 gain in dB.
+  pub gain: Option<f64>,
+}
+
+impl LoudnessStats {
+  fn new(integrated: f64, loudness_range: f64, true_peak: f64) -> Self {
+    let integrated = Some(integrated).filter(|l| l.is_finite());
+
+    return Self {
+      integrated,
+      loudness_range,
+      true_peak,
+      gain: integrated.map(|l| REFERENCE_LOUDNESS - l),
+    };
+  }
+}
+
+#[derive(Serialize, Deserialize, Type, Clone, Debug)]
+pub struct TrackLoudness {
+  pub path: String,
+  /// `None` when the track couldn't be measured, see `error`.
+  pub stats: Option<LoudnessStats>,
+  pub tags_written: bool,
+  /// Why the track couldn't be measured or tagged. The rest of the batch
+  /// goes on without it.
+  pub error: Option<String>,
+}
+
+impl TrackLoudness {
+  fn failed(path: &str, error: Error) -> Self {
+    log::warn!("loudness analysis of {} failed: {}", path, error);
+
+    return Self {
+      path: path.to_string(),
+      stats: None,
+      tags_written: false,
+      error: Some(error.to_string()),
+    };
+  }
+}
+
+#[derive(Serialize, Deserialize, Type, Clone, Debug)]
+pub struct LoudnessReport {
+  pub tracks: Vec<TrackLoudness>,
+  /// All measured tracks as one album, when requested.
+  pub album: Option<LoudnessStats>,
+}
+
+/// Runs an EBU R128 analysis over `paths`. With `album`, the tracks are also
+/// measured together as one album. With `write_tags`, ReplayGain 2.0 frames
+/// are written to files that carry ID3 tags; other files are skipped. A file
+/// that fails is reported in its own entry instead of failing the batch.
+#[tauri::command]
+#[specta::specta]
+pub async fn analyze_loudness(
+  app_handle: AppHandle<tauri::Wry>,
+  paths: Vec<String>,
+  album: bool,
+  write_tags: bool,
+) -> Result<LoudnessReport> {
+  return spawn_blocking(move || {
+    let mut cache = LoudnessCache::new(&app_handle);
+
+    return analyze(&mut cache, paths, album, write_tags);
+  })
+  .await
+  .map_err(|e| Error::Audio(e.to_string()))?;
+}
+
+fn analyze(
+  cache: &mut LoudnessCache,
+  paths: Vec<String>,
+  album: bool,
+  write_tags: bool,
+) -> Result<LoudnessReport> {
+  let mut tracks = Vec::with_capacity(paths.len());
+  let mut readable = Vec::with_capacity(paths.len());
+  let mut signatures = Vec::with_capacity(paths.len());
+
+  for path in &paths {
+    match FileSignature::read(path) {
+      Ok(signature) => {
+        readable.push(path.clone());
+        signatures.push(signature);
+      }
+      Err(e) => tracks.push(TrackLoudness::failed(path, e)),
+    }
+  }
+
+  let album_key = (album && !readable.is_empty()).then(|| build_album_key(&readable, &signatures));
+  let cached_album = album_key.as_deref().and_then(|key| cache.album(key));
+  // album loudness is gated over the blocks of every track, so a cached album
+  // is the only way around decoding all of them again
+  let keep_meters = album_key.is_some() && cached_album.is_none();
+
+  let mut meters = Vec::new();
+
+  for (path, signature) in readable.iter().zip(&signatures) {
+    let cached = cache.track(path, signature);
+
+    let stats = match cached {
+      Some(stats) if !keep_meters => stats,
+      _ => {
+        let measured = measure(path).and_then(|meter| Ok((track_stats(&meter)?, meter)));
+        let (stats, meter) = match measured {
+          Ok(measured) => measured,
+          Err(e) => {
+            tracks.push(TrackLoudness::failed(path, e));
+            continue;
+          }
+        };
+        cache.store_track(path, signature, &stats);
+
+        if keep_meters {
+          meters.push(meter);
+        }
+
+        stats
+      }
+    };
+
+    tracks.push(TrackLoudness {
+      path: path.clone(),
+      stats: Some(stats),
+      tags_written: false,
+      error: None,
+    });
+  }
+
+  // failed tracks aren't part of the album, so it can't be cached under the
+  // key of the whole set
+  let complete = meters.len() == readable.len();
+  let album_stats = match (album_key.as_deref(), cached_album) {
+    (Some(_), Some(stats)) => Some(stats),
+    (Some(_), None) if meters.is_empty() => None,
+    (Some(key), None) => match album_stats(&meters, &tracks) {
+      Ok(stats) => {
+        if complete {
+          cache.store_album(key, &stats);
+        }
+        Some(stats)
+      }
+      Err(e) => {
+        log::warn!("album loudness analysis failed: {}", e);
+        None
+      }
+    },
+    (None, _) => None,
+  };
+
+  if write_tags {
+    write_tags_to_files(cache, &mut tracks, album_stats.as_ref());
+  }
+
+  // back in the order the paths were given
+  tracks.sort_by_key(|track| paths.iter().position(|path| *path == track.path));
+
+  return Ok(LoudnessReport {
+    tracks,
+    album: album_stats,
+  });
+}
+
+fn measure(path: &str) -> Result<EbuR128> {
+  let mut meter: Option<EbuR128> = None;
+
+  decode_interleaved(path, |spec, samples| {
+    let channels = spec.channels.count() as u32;
+
+    if meter.is_none() {
+      let new_meter = EbuR128::new(channels, spec.rate, Mode::I | Mode::LRA | Mode::TRUE_PEAK)
+        .map_err(loudness_error)?;
+      meter = Some(new_meter);
+    }
+    let Some(meter) = meter.as_mut() else {
+      return Ok(());
+    };
+
+    if meter.channels() != channels || meter.rate() != spec.rate {
+      return Err(Error::Audio(
+        "loudness analysis failed: stream format changed mid-file".to_string(),
+      ));
+    }
+
+    return meter.add_frames_f32(samples).map_err(loudness_error);
+  })?;
+
+  return meter.ok_or(Error::Audio(
+    "loudness analysis failed: no audio decoded".to_string(),
+  ));
+}
+
+fn track_stats(meter: &EbuR128) -> Result<LoudnessStats> {
+  let integrated = meter.loudness_global().map_err(loudness_error)?;
+  let loudness_range = meter.loudness_range().map_err(loudness_error)?;
+
+  let mut true_peak: f64 = 0.0;
+  for channel in 0..meter.channels() {
+    true_peak = true_peak.max(meter.true_peak(channel).map_err(loudness_error)?);
+  }
+
+  return Ok(LoudnessStats::new(integrated, loudness_range, true_peak));
+}
+
+fn album_stats(meters: &[EbuR128], tracks: &[TrackLoudness]) -> Result<LoudnessStats> {
+  let integrated = EbuR128::loudness_global_multiple(meters.iter()).map_err(loudness_error)?;
+  let loudness_range = EbuR128::loudness_range_multiple(meters.iter()).map_err(loudness_error)?;
+  let true_peak = tracks
+    .iter()
+    .filter_map(|track| track.stats)
+    .map(|stats| stats.true_peak)
+    .fold(0.0, f64::max);
+
+  return Ok(LoudnessStats::new(integrated, loudness_range, true_peak));
+}
+
+fn write_tags_to_files(
+  cache: &mut LoudnessCache,
+  tracks: &mut [TrackLoudness],
+  album: Option<&LoudnessStats>,
+) {
+  let mut paths = Vec::with_capacity(tracks.len());
+  let mut signatures = Vec::with_capacity(tracks.len());
+
+  for track in tracks.iter_mut() {
+    let Some(stats) = track.stats else {
+      continue;
+    };
+
+    if can_write_id3(&track.path) {
+      let replay_gain = ReplayGain {
+        track_gain: stats.gain.map(|g| g as f32),
+        track_peak: Some(stats.true_peak as f32),
+        album_gain: album.and_then(|a| a.gain).map(|g| g as f32),
+        album_peak: album.map(|a| a.true_peak as f32),
+      };
+
+      match write_replay_gain_frames(&track.path, &replay_gain) {
+        Ok(()) => track.tags_written = true,
+        Err(e) => {
+          log::warn!("failed to write replaygain tags to {}: {}", track.path, e);
+          track.error = Some(e.to_string());
+          continue;
+        }
+      }
+    } else {
+      log::info!(
+        "skipping replaygain tags for {}: no id3 support",
+        track.path
+      );
+    }
+
+    // writing tags changes size and mtime, keep the cache pointing at the new file
+    let signature = match FileSignature::read(&track.path) {
+      Ok(signature) => signature,
+      Err(e) => {
+        log::warn!("failed to read {} after tagging: {}", track.path, e);
+        continue;
+      }
+    };
+    cache.store_track(&track.path, &signature, &stats);
+
+    paths.push(track.path.clone());
+    signatures.push(signature);
+  }
+
+  // only a set that was tagged as a whole gets its album entry back
+  if let Some(album) = album.filter(|_| paths.len() == tracks.len()) {
+    cache.store_album(&build_album_key(&paths, &signatures), album);
+  }
+}
+
+fn loudness_error(error: ebur128::Error) -> Error {
+  return Error::Audio(format!("loudness analysis failed: {}", error));
+}
+
+/// Identifies a set of files regardless of order. Any file changing on disk
+/// changes the key.
+fn build_album_key(paths: &[String], signatures: &[FileSignature]) -> String {
+  let mut entries: Vec<String> = paths
+    .iter()
+    .zip(signatures)
+    .map(|(path, signature)| format!("{}\0{}\0{}", path, signature.size, signature.modified_at))
+    .collect();
+  entries.sort();
+
+  return format!("{:x}", md5::compute(entries.join("\n")));
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = track_loudness)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct TrackLoudnessRow {
+  path: String,
+  file_size: i64,
+  modified_at: i64,
+  integrated: Option<f64>,
+  loudness_range: f64,
+  true_peak: f64,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = album_loudness)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct AlbumLoudnessRow {
+  album_key: String,
+  integrated: Option<f64>,
+  loudness_range: f64,
+  true_peak: f64,
+}
+
+/// Results cached in SQLite. The cache is best effort: an unavailable
+/// database or a missing table only means tracks get measured again.
+struct LoudnessCache {
+  conn: Option<PooledConnection<ConnectionManager<SqliteConnection>>>,
+}
+
+impl LoudnessCache {
+  fn new(app_handle: &AppHandle<tauri::Wry>) -> Self {
+    let conn = app_handle.try_state::<DbPool>().and_then(|pool| {
+      pool
+        .get()
+        .inspect_err(|e| log::warn!("loudness cache unavailable: {e}"))
+        .ok()
+    });
+
+    return Self { conn };
+  }
+
+  fn track(&mut self, path: &str, signature: &FileSignature) -> Option<LoudnessStats> {
+    let conn = self.conn.as_mut()?;
+
+    let row = track_loudness::table
+      .find(path)
+      .filter(track_loudness::file_size.eq(signature.size))
+      .filter(track_loudness::modified_at.eq(signature.modified_at))
+      .select(TrackLoudnessRow::as_select())
+      .first(conn)
+      .optional()
+      .inspect_err(|e| log::warn!("failed to read loudness cache: {e}"))
+      .ok()??;
+
+    return Some(LoudnessStats::new(
+      row.integrated.unwrap_or(f64::NEG_INFINITY),
+      row.loudness_range,
+      row.true_peak,
+    ));
+  }
+
+  fn store_track(&mut self, path: &str, signature: &FileSignature, stats: &LoudnessStats) {
+    let Some(conn) = self.conn.as_mut() else {
+      return;
+    };
+
+    let row = TrackLoudnessRow {
+      path: path.to_string(),
+      file_size: signature.size,
+      modified_at: signature.modified_at,
+      integrated: stats.integrated,
+      loudness_range: stats.loudness_range,
+      true_peak: stats.true_peak,
+    };
+
+    if let Err(e) = diesel::replace_into(track_loudness::table)
+      .values(&row)
+      .execute(conn)
+    {
+      log::warn!("failed to write loudness cache: {e}");
+    }
+  }
+
+  fn album(&mut self, key: &str) -> Option<LoudnessStats> {
+    let conn = self.conn.as_mut()?;
+
+    let row = album_loudness::table
+      .find(key)
+      .select(AlbumLoudnessRow::as_select())
+      .first(conn)
+      .optional()
+      .inspect_err(|e| log::warn!("failed to read loudness cache: {e}"))
+      .ok()??;
+
+    return Some(LoudnessStats::new(
+      row.integrated.unwrap_or(f64::NEG_INFINITY),
+      row.loudness_range,
+      row.true_peak,
+    ));
+  }
+
+  fn store_album(&mut self, key: &str, stats: &LoudnessStats) {
+    let Some(conn) = self.conn.as_mut() else {
+      return;
+    };
+
+    let row = AlbumLoudnessRow {
+      album_key: key.to_string(),
+      integrated: stats.integrated,
+      loudness_range: stats.loudness_range,
+      true_peak: stats.true_peak,
+    };
+
+    if let Err(e) = diesel::replace_into(album_loudness::table)
+      .values(&row)
+      .execute(conn)
+    {
+      log::warn!("failed to write loudness cache: {e}");
+    }
+  }
+}
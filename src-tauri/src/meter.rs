@@ -0,0 +1,267 @@
+use crate::error::{Error, Result};
+use kira::effect::{Effect, EffectBuilder};
+use kira::info::Info;
+use kira::Frame;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::ipc::{Channel, InvokeResponseBody, IpcResponse};
+
+const MIN_FFT_SIZE: u32 = 256;
+const MAX_FFT_SIZE: u32 = 16384;
+const MIN_RATE: f64 = 1.0;
+const MAX_RATE: f64 = 120.0;
+// spectrum floor, keeps silence from turning into -inf
+const MIN_DB: f32 = -120.0;
+
+/// Tap shared by every equalizer track built, including ones rebuilt after an
+/// output change, and the analysis thread of the current subscriber.
+pub static METER_TAP: LazyLock<Arc<MeterTap>> = LazyLock::new(|| Arc::new(MeterTap::new()));
+
+static SUBSCRIPTION: Mutex<Option<Arc<AtomicBool>>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize, Type, Clone, Copy, Debug)]
+pub struct MeterSettings {
+  /// Packets per second.
+  pub rate: f64,
+  /// FFT length in frames, rounded to a power of two.
+  pub fft_size: u32,
+}
+
+/// Binary meter packet, received as an `ArrayBuffer` of little-endian `f32`s:
+/// sample rate, peak left, peak right, RMS left, RMS right, then
+/// `fft_size / 2` spectrum bins in dBFS from 0 Hz up. Levels are linear and
+/// cover everything played since the previous packet.
+#[derive(Type)]
+pub struct MeterPacket(Vec<u8>);
+
+impl IpcResponse for MeterPacket {
+  fn body(self) -> tauri::Result<InvokeResponseBody> {
+    return Ok(InvokeResponseBody::Raw(self.0));
+  }
+}
+
+#[derive(Default)]
+struct Levels {
+  peak: [f32; 2],
+  sum_squares: [f64; 2],
+  frames: u64,
+}
+
+struct TapBuffer {
+  ring: Vec<Frame>,
+  write: usize,
+  levels: Levels,
+}
+
+pub struct MeterTap {
+  /// Set while someone is subscribed. The effect does nothing otherwise.
+  active: AtomicBool,
+  sample_rate: AtomicU32,
+  buffer: Mutex<TapBuffer>,
+}
+
+impl MeterTap {
+  fn new() -> Self {
+    return Self {
+      active: AtomicBool::new(false),
+      sample_rate: AtomicU32::new(0),
+      buffer: Mutex::new(TapBuffer {
+        ring: vec![Frame::ZERO; MAX_FFT_SIZE as usize],
+        write: 0,
+        levels: Levels::default(),
+      }),
+    };
+  }
+
+  /// Latest `out.len()` frames, oldest first, and the levels since the last
+  /// call.
+  fn take(&self, out: &mut [Frame]) -> Levels {
+    let mut buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+
+    let len = buffer.ring.len();
+    let start = buffer.write + len - out.len();
+    for (i, frame) in out.iter_mut().enumerate() {
+      *frame = buffer.ring[(start + i) % len];
+    }
+
+    return std::mem::take(&mut buffer.levels);
+  }
+}
+
+/// Builds the effect feeding [`METER_TAP`]. Belongs at the end of the chain so
+/// it sees what is actually heard.
+pub struct MeterTapBuilder;
+
+impl EffectBuilder for MeterTapBuilder {
+  type Handle = ();
+
+  fn build(self) -> (Box<dyn Effect>, Self::Handle) {
+    return (
+      Box::new(MeterTapEffect {
+        tap: METER_TAP.clone(),
+      }),
+      (),
+    );
+  }
+}
+
+struct MeterTapEffect {
+  tap: Arc<MeterTap>,
+}
+
+impl Effect for MeterTapEffect {
+  fn init(&mut self, sample_rate: u32, _internal_buffer_size: usize) {
+    self.on_change_sample_rate(sample_rate);
+  }
+
+  fn on_change_sample_rate(&mut self, sample_rate: u32) {
+    self.tap.sample_rate.store(sample_rate, Ordering::Relaxed);
+  }
+
+  fn process(&mut self, input: &mut [Frame], _dt: f64, _info: &Info) {
+    if !self.tap.active.load(Ordering::Relaxed) {
+      return;
+    }
+
+    // never wait on the analysis thread, a dropped block only blips the meter
+    let Ok(mut buffer) = self.tap.buffer.try_lock() else {
+      return;
+    };
+
+    let len = buffer.ring.len();
+    for frame in input.iter() {
+      let write = buffer.write;
+      buffer.ring[write] = *frame;
+      buffer.write = (write + 1) % len;
+
+      let levels = &mut buffer.levels;
+      for (channel, sample) in [frame.left, frame.right].into_iter().enumerate() {
+        levels.peak[channel] = levels.peak[channel].max(sample.abs());
+        levels.sum_squares[channel] += (sample as f64) * (sample as f64);
+      }
+      levels.frames += 1;
+    }
+  }
+}
+
+/// Streams meter packets to `channel` until `unsubscribe_meter` is called or
+/// the channel goes away. A new subscription replaces the previous one.
+#[tauri::command]
+#[specta::specta]
+pub fn subscribe_meter(settings: MeterSettings, channel: Channel<MeterPacket>) -> Result<()> {
+  let fft_size = settings
+    .fft_size
+    .clamp(MIN_FFT_SIZE, MAX_FFT_SIZE)
+    .next_power_of_two() as usize;
+  let interval = Duration::from_secs_f64(1.0 / settings.rate.clamp(MIN_RATE, MAX_RATE));
+
+  let stop = Arc::new(AtomicBool::new(false));
+  if let Some(previous) = replace_subscription(Some(stop.clone())) {
+    previous.store(true, Ordering::Relaxed);
+  }
+  METER_TAP.active.store(true, Ordering::Relaxed);
+
+  thread::Builder::new()
+    .name("meter".to_string())
+    .spawn(move || {
+      let mut analyzer = Analyzer::new(fft_size);
+
+      while !stop.load(Ordering::Relaxed) {
+        thread::sleep(interval);
+
+        if channel.send(analyzer.packet(&METER_TAP)).is_err() {
+          break;
+        }
+      }
+
+      // only the latest subscription may switch the tap off
+      let mut subscription = SUBSCRIPTION.lock().unwrap_or_else(|e| e.into_inner());
+      if subscription.as_ref().is_some_and(|s| Arc::ptr_eq(s, &stop)) {
+        *subscription = None;
+        METER_TAP.active.store(false, Ordering::Relaxed);
+      }
+    })
+    .map_err(|e| Error::Audio(format!("failed to start meter: {}", e)))?;
+
+  return Ok(());
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn unsubscribe_meter() {
+  if let Some(stop) = replace_subscription(None) {
+    stop.store(true, Ordering::Relaxed);
+  }
+  METER_TAP.active.store(false, Ordering::Relaxed);
+}
+
+fn replace_subscription(next: Option<Arc<AtomicBool>>) -> Option<Arc<AtomicBool>> {
+  let mut subscription = SUBSCRIPTION.lock().unwrap_or_else(|e| e.into_inner());
+  return std::mem::replace(&mut *subscription, next);
+}
+
+struct Analyzer {
+  fft: Arc<dyn rustfft::Fft<f32>>,
+  window: Vec<f32>,
+  frames: Vec<Frame>,
+  spectrum: Vec<Complex<f32>>,
+  scratch: Vec<Complex<f32>>,
+  // brings a full scale sine to 0 dB
+  scale: f32,
+}
+
+impl Analyzer {
+  fn new(size: usize) -> Self {
+    let fft = FftPlanner::new().plan_fft_forward(size);
+    let window: Vec<f32> = (0..size)
+      .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / size as f32).cos())
+      .collect();
+    let scale = 2.0 / window.iter().sum::<f32>();
+
+    return Self {
+      scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+      fft,
+      window,
+      frames: vec![Frame::ZERO; size],
+      spectrum: vec![Complex::default(); size],
+      scale,
+    };
+  }
+
+  fn packet(&mut self, tap: &MeterTap) -> MeterPacket {
+    let levels = tap.take(&mut self.frames);
+
+    for ((bin, frame), window) in self.spectrum.iter_mut().zip(&self.frames).zip(&self.window) {
+      *bin = Complex::new((frame.left + frame.right) * 0.5 * window, 0.0);
+    }
+    self
+      .fft
+      .process_with_scratch(&mut self.spectrum, &mut self.scratch);
+
+    let rms = |channel: usize| -> f32 {
+      if levels.frames == 0 {
+        return 0.0;
+      }
+      return (levels.sum_squares[channel] / levels.frames as f64).sqrt() as f32;
+    };
+
+    let bins = self.spectrum.len() / 2;
+    let mut values = Vec::with_capacity(5 + bins);
+    values.push(tap.sample_rate.load(Ordering::Relaxed) as f32);
+    values.extend([levels.peak[0], levels.peak[1], rms(0), rms(1)]);
+    values.extend(
+      self.spectrum[..bins]
+        .iter()
+        .map(|bin| (20.0 * (bin.norm() * self.scale).log10()).max(MIN_DB)),
+    );
+
+    return MeterPacket(values.into_iter().flat_map(f32::to_le_bytes).collect());
+  }
+}
@@ -0,0 +1,156 @@
+use crate::error::{Error, Result};
+use crate::playback::OutputSettings;
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{BufferSize, Device, DeviceId, StreamConfig};
+use kira::backend::cpal::CpalBackendSettings;
+use kira::{AudioManager, AudioManagerSettings, DefaultBackend};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use std::str::FromStr;
+use tauri::async_runtime::spawn_blocking;
+
+const MIN_BUFFER_SIZE: u32 = 32;
+const MAX_BUFFER_SIZE: u32 = 8192;
+// offered in `OutputDevice::sample_rates` when the device supports them
+const COMMON_SAMPLE_RATES: [u32; 6] = [44_100, 48_000, 88_200, 96_000, 176_400, 192_000];
+
+#[derive(Serialize, Clone, Deserialize, Type, Debug)]
+pub struct OutputDevice {
+  /// Stable identifier, passed back through `StreamAction::SetOutputDevice`.
+  pub id: String,
+  pub name: String,
+  pub is_default: bool,
+  /// Rate the device runs at by default, used unless
+  /// `OutputSettings::sample_rate` picks another one.
+  pub sample_rate: Option<u32>,
+  /// Common rates the device supports, for `StreamAction::SetSampleRate`.
+  pub sample_rates: Vec<u32>,
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn list_output_devices() -> Result<Vec<OutputDevice>> {
+  return spawn_blocking(move || {
+    let host = cpal::default_host();
+    let default_id = host.default_output_device().and_then(|d| d.id().ok());
+
+    let devices = host
+      .output_devices()
+      .map_err(|e| Error::Audio(format!("failed to list output devices: {}", e)))?;
+
+    let devices = devices
+      .filter_map(|device| {
+        let id = device.id().ok()?;
+        let name = device
+          .description()
+          .map(|description| description.name().to_string())
+          .unwrap_or_else(|_| id.to_string());
+
+        return Some(OutputDevice {
+          is_default: default_id.as_ref() == Some(&id),
+          id: id.to_string(),
+          name,
+          sample_rate: device
+            .default_output_config()
+            .ok()
+            .map(|config| config.sample_rate()),
+          sample_rates: COMMON_SAMPLE_RATES
+            .into_iter()
+            .filter(|&rate| output_config(&device, Some(rate)).is_ok())
+            .collect(),
+        });
+      })
+      .collect();
+
+    return Ok(devices);
+  })
+  .await
+  .map_err(|e| Error::Audio(e.to_string()))?;
+}
+
+pub fn find_output_device(id: &str) -> Option<Device> {
+  let id = DeviceId::from_str(id).ok()?;
+
+  return cpal::default_host()
+    .device_by_id(&id)
+    .filter(|device| device.supports_output());
+}
+
+/// Opens the audio manager on the selected device. Falls back to the default
+/// device when the selected one is gone, returning `true` in that case.
+pub fn open_audio_manager(
+  settings: &OutputSettings,
+) -> Result<(AudioManager<DefaultBackend>, bool)> {
+  let device = settings.device.as_deref().map(find_output_device);
+  let is_fallback = matches!(device, Some(None));
+  let device = device.flatten();
+
+  let output_device = resolve_device(device.as_ref())?;
+  // the rate was checked against the device it was picked for, which
+  // shouldn't keep another one from opening
+  let mut config = match output_config(&output_device, settings.sample_rate) {
+    Ok(config) => config,
+    Err(e) => {
+      log::warn!("{}, using the default rate", e);
+      output_config(&output_device, None)?
+    }
+  };
+  config.buffer_size = match settings.buffer_size {
+    Some(frames) => BufferSize::Fixed(frames.clamp(MIN_BUFFER_SIZE, MAX_BUFFER_SIZE)),
+    None => BufferSize::Default,
+  };
+
+  let audio_manager = AudioManager::new(AudioManagerSettings {
+    backend_settings: CpalBackendSettings {
+      // `None` lets kira follow the system default around
+      device,
+      config: Some(config),
+    },
+    internal_buffer_size: 256,
+    ..Default::default()
+  })
+  .map_err(|_| Error::Audio("failed to create audio manager".to_string()))?;
+
+  return Ok((audio_manager, is_fallback));
+}
+
+/// Fails when the device `settings` select can't run at their sample rate.
+pub fn check_sample_rate(settings: &OutputSettings) -> Result<()> {
+  let device = settings.device.as_deref().and_then(find_output_device);
+
+  return output_config(&resolve_device(device.as_ref())?, settings.sample_rate).map(drop);
+}
+
+fn resolve_device(device: Option<&Device>) -> Result<Device> {
+  return match device {
+    Some(device) => Ok(device.clone()),
+    None => cpal::default_host()
+      .default_output_device()
+      .ok_or(Error::Audio("no output device available".to_string())),
+  };
+}
+
+/// The device's default config, moved to `sample_rate` when there is one.
+fn output_config(device: &Device, sample_rate: Option<u32>) -> Result<StreamConfig> {
+  let default = device
+    .default_output_config()
+    .map_err(|e| Error::Audio(format!("failed to read output config: {}", e)))?;
+
+  let Some(sample_rate) = sample_rate else {
+    return Ok(default.config());
+  };
+
+  let supported = device
+    .supported_output_configs()
+    .map_err(|e| Error::Audio(format!("failed to read output configs: {}", e)))?
+    .filter(|range| {
+      range.channels() == default.channels() && range.sample_format() == default.sample_format()
+    })
+    .find_map(|range| range.try_with_sample_rate(sample_rate))
+    .ok_or(Error::Audio(format!(
+      "output device doesn't support {} Hz",
+      sample_rate
+    )))?;
+
+  return Ok(supported.config());
+}
@@ -0,0 +1,114 @@
+use kira::effect::{Effect, EffectBuilder};
+use kira::info::Info;
+use kira::Frame;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// length of one grain. longer smooths out warbling on tonal material at the
+// cost of smeared transients
+const WINDOW_SECONDS: f64 = 0.05;
+
+/// Builds a [`PitchShifter`]. The ratio starts at `1.0`, which bypasses it.
+pub struct PitchShifterBuilder;
+
+impl EffectBuilder for PitchShifterBuilder {
+  type Handle = PitchShifterHandle;
+
+  fn build(self) -> (Box<dyn Effect>, Self::Handle) {
+    let ratio = Arc::new(AtomicU64::new(1.0f64.to_bits()));
+
+    let effect = PitchShifter {
+      ratio: ratio.clone(),
+      buffer: Vec::new(),
+      write: 0,
+      delay: 0.0,
+    };
+
+    return (Box::new(effect), PitchShifterHandle { ratio });
+  }
+}
+
+pub struct PitchShifterHandle {
+  ratio: Arc<AtomicU64>,
+}
+
+impl PitchShifterHandle {
+  /// Sets the pitch ratio, e.g. `0.5` for an octave down.
+  pub fn set_ratio(&mut self, ratio: f64) {
+    self.ratio.store(ratio.to_bits(), Ordering::Relaxed);
+  }
+}
+
+/// Delay-line pitch shifter: two read taps sweep through a short buffer at a
+/// speed set by the ratio, each faded by a hann window so one tap is silent
+/// while it wraps around. Used to undo the pitch change of a playback rate.
+struct PitchShifter {
+  ratio: Arc<AtomicU64>,
+  buffer: Vec<Frame>,
+  write: usize,
+  /// Delay of the first tap in frames, within `0..buffer.len()`.
+  delay: f64,
+}
+
+impl PitchShifter {
+  fn read(&self, delay: f64) -> Frame {
+    let len = self.buffer.len();
+    let position = (self.write + len) as f64 - delay;
+    let index = position.floor() as usize;
+    let fraction = (position - position.floor()) as f32;
+
+    let a = self.buffer[index % len];
+    let b = self.buffer[(index + 1) % len];
+
+    return a + (b - a) * fraction;
+  }
+}
+
+impl Effect for PitchShifter {
+  fn init(&mut self, sample_rate: u32, _internal_buffer_size: usize) {
+    self.on_change_sample_rate(sample_rate);
+  }
+
+  fn on_change_sample_rate(&mut self, sample_rate: u32) {
+    let len = (sample_rate as f64 * WINDOW_SECONDS).round().max(2.0) as usize;
+
+    self.buffer = vec![Frame::ZERO; len];
+    self.write = 0;
+    self.delay = 0.0;
+  }
+
+  fn process(&mut self, input: &mut [Frame], _dt: f64, _info: &Info) {
+    let len = self.buffer.len();
+    if len == 0 {
+      return;
+    }
+
+    let ratio = f64::from_bits(self.ratio.load(Ordering::Relaxed));
+    let bypass = (ratio - 1.0).abs() < 1e-3;
+    let window = len as f64;
+
+    for frame in input.iter_mut() {
+      self.buffer[self.write] = *frame;
+
+      // both taps at a fixed delay would comb filter, so pass the input through
+      if !bypass {
+        let first = self.delay;
+        let second = (self.delay + window / 2.0) % window;
+
+        let first_gain = hann(first / window);
+        let second_gain = hann(second / window);
+
+        *frame = self.read(first) * first_gain + self.read(second) * second_gain;
+
+        self.delay = (self.delay + 1.0 - ratio).rem_euclid(window);
+      }
+
+      self.write = (self.write + 1) % len;
+    }
+  }
+}
+
+fn hann(phase: f64) -> f32 {
+  return 0.5 - 0.5 * (2.0 * PI * phase as f32).cos();
+}
@@ -1,10 +1,28 @@
 #![deny(clippy::unwrap_used, clippy::expect_used)]
 use crate::error::{Error, Result};
+use crate::read::ReplayGain;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tauri::{AppHandle, Manager};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager, Runtime};
+use tauri_plugin_store::StoreExt;
+use tauri_specta::Event;
 use tokio::sync::{mpsc, oneshot};
 
+// how many previously played paths are kept for `StreamAction::Previous`
+const QUEUE_HISTORY_LIMIT: usize = 500;
+// entries kept in the persisted queue, played ones are dropped first
+const QUEUE_TRACK_LIMIT: usize = 10_000;
+// prefs key the queue is saved under, apart from the status
+const QUEUE_STORE_KEY: &str = "play-queue";
+
+/// Copy of the audio thread's queue for `get_queue`, replaced whenever the
+/// queue changes.
+pub static QUEUE: Mutex<Option<Arc<PlayQueue>>> = Mutex::new(None);
+
 pub struct AudioHandle {
   pub tx: mpsc::Sender<(StreamAction, oneshot::Sender<StreamStatus>)>,
 }
@@ -12,15 +30,562 @@ pub struct AudioHandle {
 #[derive(Serialize, Clone, Deserialize, Type, Debug)]
 pub enum StreamAction {
   Play(String),
+  /// Like `Play`, but starts from the track's saved resume position if any.
+  PlayResumed(String),
+  Enqueue(String),
+  QueueAppend(Vec<String>),
+  QueueInsertNext(Vec<String>),
+  QueueRemove(u32),
+  QueueMove {
+    from: u32,
+    to: u32,
+  },
+  QueueClear,
+  QueueShuffle(Option<u32>),
+  QueuePlay(u32),
+  SetRepeat(RepeatMode),
+  Next,
+  Previous,
+  SetPositionInterval(u32),
   Pause,
   Resume,
   Seek(f64),
   SetLoop(bool),
+  SetLoopRegion {
+    start: f64,
+    end: f64,
+  },
+  ClearLoopRegion,
+  SetCrossfade(CrossfadeSettings),
+  SetReplayGain(ReplayGainSettings),
+  SetEqualizer(EqualizerSettings),
+  SaveEqPreset(String),
+  LoadEqPreset(String),
+  DeleteEqPreset(String),
   SetVolume(f32),
+  SetPlaybackRate(f64),
+  SetPreservePitch(bool),
+  SetOutputDevice(Option<String>),
+  SetBufferSize(Option<u32>),
+  SetSampleRate(Option<u32>),
+  SetSleepTimer(SleepTimer),
+  CancelSleepTimer,
+  SetFades(TransportFades),
+  SetStaticMemoryBudget(u32),
+  SetResume(ResumeSettings),
+  /// Where the tracks being played were picked from, recorded with each listen.
+  SetSource(Option<PlaySource>),
+  SetPlayCount(PlayCountSettings),
   ToggleMute,
   Reset,
 }
 
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, Default, PartialEq)]
+pub enum FadeCurve {
+  #[default]
+  Linear,
+  EaseIn,
+  EaseOut,
+  EaseInOut,
+}
+
+#[derive(Serialize, Clone, Deserialize, Type, Debug, Default)]
+pub struct CrossfadeSettings {
+  /// Overlap between consecutive tracks in seconds. `0.0` keeps the gapless hand-over.
+  pub duration: f64,
+  pub curve: FadeCurve,
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, Default, PartialEq)]
+pub enum ReplayGainMode {
+  #[default]
+  Off,
+  Track,
+  Album,
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug)]
+pub struct ReplayGainSettings {
+  pub mode: ReplayGainMode,
+  /// Extra gain in dB, applied to tagged and untagged tracks alike.
+  pub preamp: f32,
+  /// Lowers the gain so the tagged peak doesn't go over full scale.
+  pub prevent_clipping: bool,
+}
+
+impl Default for ReplayGainSettings {
+  fn default() -> Self {
+    return Self {
+      mode: ReplayGainMode::Off,
+      preamp: 0.0,
+      prevent_clipping: true,
+    };
+  }
+}
+
+impl ReplayGainSettings {
+  /// Gain offset in dB for a track with the given tags. Album mode falls back
+  /// to the track values and vice versa.
+  pub fn gain_for(&self, tags: &ReplayGain) -> f32 {
+    let (gain, peak) = match self.mode {
+      ReplayGainMode::Off => return 0.0,
+      ReplayGainMode::Track => (
+        tags.track_gain.or(tags.album_gain),
+        tags.track_peak.or(tags.album_peak),
+      ),
+      ReplayGainMode::Album => (
+        tags.album_gain.or(tags.track_gain),
+        tags.album_peak.or(tags.track_peak),
+      ),
+    };
+
+    let mut gain = gain.unwrap_or(0.0) + self.preamp;
+
+    if self.prevent_clipping {
+      if let Some(peak) = peak.filter(|p| *p > 0.0) {
+        gain = gain.min(-20.0 * peak.log10());
+      }
+    }
+
+    return gain;
+  }
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, PartialEq)]
+pub enum EqBandKind {
+  Peaking,
+  LowShelf,
+  HighShelf,
+  LowPass,
+  HighPass,
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug)]
+pub struct EqBand {
+  pub kind: EqBandKind,
+  /// Center or cutoff frequency in Hz.
+  pub frequency: f64,
+  /// Boost or cut in dB. Ignored by low-pass and high-pass bands.
+  pub gain: f32,
+  pub q: f64,
+}
+
+#[derive(Serialize, Clone, Deserialize, Type, Debug)]
+pub struct EqualizerSettings {
+  /// Bypasses the bands and preamp when off. Balance always applies.
+  pub enabled: bool,
+  /// Gain in dB applied before the bands.
+  pub preamp: f32,
+  /// Stereo balance from `-1.0` (left) to `1.0` (right).
+  pub balance: f32,
+  pub bands: Vec<EqBand>,
+}
+
+impl Default for EqualizerSettings {
+  fn default() -> Self {
+    return Self {
+      enabled: true,
+      preamp: 0.0,
+      balance: 0.0,
+      bands: Vec::new(),
+    };
+  }
+}
+
+#[derive(Serialize, Clone, Deserialize, Type, Debug)]
+pub struct EqPreset {
+  pub name: String,
+  pub settings: EqualizerSettings,
+}
+
+#[derive(Serialize, Clone, Deserialize, Type, Debug, Default, PartialEq)]
+pub struct OutputSettings {
+  /// Id from `list_output_devices`. `None` follows the system default.
+  pub device: Option<String>,
+  /// Device buffer size in frames. `None` lets the driver decide.
+  pub buffer_size: Option<u32>,
+  /// Output rate in Hz, one of `OutputDevice::sample_rates`. `None` uses the
+  /// device's default rate.
+  #[serde(default)]
+  pub sample_rate: Option<u32>,
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, PartialEq)]
+pub enum SleepTimerMode {
+  /// Pauses once this many minutes have passed.
+  Minutes(f64),
+  /// Stops after this many tracks have finished, counting the current one.
+  Tracks(u32),
+  EndOfTrack,
+  /// Stops when the next track in the queue belongs to another album.
+  EndOfAlbum,
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, PartialEq)]
+pub struct SleepTimer {
+  pub mode: SleepTimerMode,
+  /// Seconds to fade out over before stopping. `0.0` stops abruptly.
+  pub fade_out: f64,
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, PartialEq)]
+pub struct SleepTimerStatus {
+  pub timer: SleepTimer,
+  /// When a `Minutes` timer runs out, in milliseconds since the unix epoch.
+  pub ends_at: Option<f64>,
+  /// Tracks still to finish, counting the current one.
+  pub tracks_left: Option<u32>,
+}
+
+/// Fade lengths in seconds for transport actions. `0.0` switches instantly and
+/// leaves the samples untouched.
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, PartialEq)]
+pub struct TransportFades {
+  pub pause: f64,
+  pub resume: f64,
+  /// Used when a track is stopped or replaced by another one.
+  pub stop: f64,
+  /// Fades out, jumps once silent and fades back in, so the jump is delayed
+  /// by this long.
+  pub seek: f64,
+}
+
+impl Default for TransportFades {
+  fn default() -> Self {
+    return Self {
+      pause: 0.05,
+      resume: 0.05,
+      stop: 0.05,
+      seek: 0.02,
+    };
+  }
+}
+
+/// When a listen counts as a play, following the usual scrobble rules: long
+/// enough tracks count once either threshold is reached.
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, PartialEq)]
+pub struct PlayCountSettings {
+  pub enabled: bool,
+  /// Share of the track in percent.
+  pub min_percent: f64,
+  /// Seconds listened, for tracks where the percentage would take very long.
+  pub min_seconds: f64,
+  /// Tracks up to this many seconds long never count.
+  pub min_duration: f64,
+}
+
+impl Default for PlayCountSettings {
+  fn default() -> Self {
+    return Self {
+      enabled: true,
+      min_percent: 50.0,
+      min_seconds: 240.0,
+      min_duration: 30.0,
+    };
+  }
+}
+
+impl PlayCountSettings {
+  pub fn counts(&self, listened: f64, duration: f64) -> bool {
+    if !self.enabled || duration <= self.min_duration {
+      return false;
+    }
+
+    return listened >= duration * self.min_percent / 100.0 || listened >= self.min_seconds;
+  }
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaySourceType {
+  Folder,
+  Playlist,
+}
+
+impl PlaySourceType {
+  pub fn as_str(&self) -> &'static str {
+    return match self {
+      PlaySourceType::Folder => "folder",
+      PlaySourceType::Playlist => "playlist",
+    };
+  }
+
+  pub fn parse(value: &str) -> Option<Self> {
+    return match value {
+      "folder" => Some(PlaySourceType::Folder),
+      "playlist" => Some(PlaySourceType::Playlist),
+      _ => None,
+    };
+  }
+}
+
+/// Same pairs as `library_tracks_source`.
+#[derive(Serialize, Clone, Deserialize, Type, Debug, PartialEq)]
+pub struct PlaySource {
+  pub source_type: PlaySourceType,
+  /// Folder path or playlist id.
+  pub source_id: String,
+}
+
+/// Remembering where long tracks were left, e.g. audiobooks and lectures.
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, PartialEq)]
+pub struct ResumeSettings {
+  pub enabled: bool,
+  /// Tracks shorter than this many seconds always start from the top.
+  pub min_duration: f64,
+}
+
+impl Default for ResumeSettings {
+  fn default() -> Self {
+    return Self {
+      enabled: true,
+      min_duration: 20.0 * 60.0,
+    };
+  }
+}
+
+/// Section of the current track to loop, in seconds.
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, PartialEq)]
+pub struct LoopRegion {
+  pub start: f64,
+  pub end: f64,
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, Default, PartialEq)]
+pub enum RepeatMode {
+  #[default]
+  Off,
+  One,
+  All,
+}
+
+/// Play queue owned by the audio thread, so playback keeps advancing when the
+/// webview is throttled or reloaded.
+#[derive(Serialize, Clone, Deserialize, Type, Debug, Default)]
+pub struct PlayQueue {
+  pub tracks: Vec<String>,
+  /// Index into `tracks` of the loaded track.
+  #[specta(type = Option<u32>)]
+  pub current: Option<usize>,
+  pub repeat: RepeatMode,
+  /// Seed of the last shuffle, so an order can be reproduced.
+  pub shuffle_seed: Option<u32>,
+  /// Previously played paths, most recent last.
+  pub history: Vec<String>,
+}
+
+/// The part of the queue carried by `StreamStatus`. The tracks themselves
+/// come from `get_queue` and `QueueChanged`.
+#[derive(Serialize, Clone, Copy, Deserialize, Type, Debug, Default, PartialEq)]
+pub struct QueueStatus {
+  /// Index of the loaded track in the queue.
+  pub current: Option<u32>,
+  pub repeat: RepeatMode,
+  #[serde(default)]
+  pub len: u32,
+}
+
+impl PlayQueue {
+  pub fn status(&self) -> QueueStatus {
+    return QueueStatus {
+      current: self.current.map(|i| i as u32),
+      repeat: self.repeat,
+      len: self.tracks.len() as u32,
+    };
+  }
+
+  pub fn append(&mut self, paths: Vec<String>) {
+    self.tracks.extend(paths);
+    self.truncate();
+  }
+
+  pub fn insert_next(&mut self, paths: Vec<String>) {
+    let at = self.current.map_or(0, |i| i + 1);
+    self.tracks.splice(at..at, paths);
+    self.truncate();
+  }
+
+  /// Removes an upcoming or past entry. The loaded track can't be removed.
+  pub fn remove(&mut self, index: usize) -> Option<String> {
+    if index >= self.tracks.len() || self.current == Some(index) {
+      return None;
+    }
+
+    let removed = self.tracks.remove(index);
+    if let Some(current) = self.current.as_mut() {
+      if index < *current {
+        *current -= 1;
+      }
+    }
+
+    return Some(removed);
+  }
+
+  pub fn move_track(&mut self, from: usize, to: usize) {
+    if from >= self.tracks.len() || to >= self.tracks.len() {
+      return;
+    }
+
+    let path = self.tracks.remove(from);
+    self.tracks.insert(to, path);
+
+    self.current = self.current.map(|current| {
+      if current == from {
+        to
+      } else if from < current && current <= to {
+        current - 1
+      } else if to <= current && current < from {
+        current + 1
+      } else {
+        current
+      }
+    });
+  }
+
+  pub fn clear(&mut self) {
+    self.tracks.clear();
+    self.current = None;
+  }
+
+  /// Shuffles everything after the loaded track. A random seed is picked when
+  /// none is given.
+  pub fn shuffle(&mut self, seed: Option<u32>) {
+    let seed = seed.unwrap_or_else(|| rand::rng().next_u32());
+    let mut rng = StdRng::seed_from_u64(seed as u64);
+
+    let from = self.current.map_or(0, |i| i + 1);
+    if from < self.tracks.len() {
+      self.tracks[from..].shuffle(&mut rng);
+    }
+
+    self.shuffle_seed = Some(seed);
+  }
+
+  /// Path that plays once the loaded track ends on its own.
+  pub fn peek_next(&self) -> Option<&String> {
+    return self.next_index(true).and_then(|i| self.tracks.get(i));
+  }
+
+  /// Moves to the next track because the loaded one ended.
+  pub fn advance(&mut self) -> Option<String> {
+    let index = self.next_index(true)?;
+    return self.jump(index);
+  }
+
+  /// Moves to the next track on user request, ignoring repeat-one.
+  pub fn skip(&mut self) -> Option<String> {
+    let index = self.next_index(false)?;
+    return self.jump(index);
+  }
+
+  pub fn jump(&mut self, index: usize) -> Option<String> {
+    let path = self.tracks.get(index)?.clone();
+
+    self.push_history();
+    self.current = Some(index);
+
+    return Some(path);
+  }
+
+  /// Makes `path` the loaded track. The upcoming entry is reused when it is the
+  /// same file, otherwise `path` takes the place of the loaded entry, which
+  /// only lives on in the history.
+  pub fn play(&mut self, path: String) {
+    let next = self.current.map_or(0, |i| i + 1);
+    if self.tracks.get(next) == Some(&path) {
+      self.push_history();
+      self.current = Some(next);
+      return;
+    }
+
+    self.push_history();
+    match self.current.and_then(|i| self.tracks.get_mut(i)) {
+      Some(loaded) => *loaded = path,
+      None => {
+        self.tracks.insert(0, path);
+        self.current = Some(0);
+        self.truncate();
+      }
+    }
+  }
+
+  /// Steps back through the history. Paths that were removed from the queue
+  /// are put back in front of the loaded track.
+  pub fn previous(&mut self) -> Option<String> {
+    let path = self.history.pop()?;
+
+    let before = self.current.and_then(|i| i.checked_sub(1));
+    let index = match before {
+      Some(i) if self.tracks.get(i) == Some(&path) => i,
+      _ => match self.tracks.iter().position(|p| *p == path) {
+        Some(i) => i,
+        None => {
+          let at = self.current.unwrap_or(0).min(self.tracks.len());
+          self.tracks.insert(at, path.clone());
+          at
+        }
+      },
+    };
+    self.current = Some(index);
+    self.truncate();
+
+    return Some(path);
+  }
+
+  /// Repairs a queue restored from disk, which may be stale or edited by
+  /// hand: an out of range `current` is dropped and both lists are capped.
+  pub fn sanitize(&mut self) {
+    if self.current.is_some_and(|i| i >= self.tracks.len()) {
+      self.current = None;
+    }
+
+    let excess = self.history.len().saturating_sub(QUEUE_HISTORY_LIMIT);
+    self.history.drain(..excess);
+    self.truncate();
+  }
+
+  /// Keeps the queue within `QUEUE_TRACK_LIMIT`, dropping played entries
+  /// first and then the far end of what is upcoming.
+  fn truncate(&mut self) {
+    let excess = self.tracks.len().saturating_sub(QUEUE_TRACK_LIMIT);
+    let played = self.current.unwrap_or(0).min(excess);
+
+    self.tracks.drain(..played);
+    if let Some(current) = self.current.as_mut() {
+      *current -= played;
+    }
+    self.tracks.truncate(QUEUE_TRACK_LIMIT);
+  }
+
+  fn next_index(&self, honour_repeat_one: bool) -> Option<usize> {
+    if honour_repeat_one && self.repeat == RepeatMode::One {
+      return self.current;
+    }
+
+    let next = self.current.map_or(0, |i| i + 1);
+    if next < self.tracks.len() {
+      return Some(next);
+    }
+
+    if self.repeat == RepeatMode::All && !self.tracks.is_empty() {
+      return Some(0);
+    }
+
+    return None;
+  }
+
+  fn push_history(&mut self) {
+    let Some(path) = self.current.and_then(|i| self.tracks.get(i)) else {
+      return;
+    };
+
+    self.history.push(path.clone());
+    if self.history.len() > QUEUE_HISTORY_LIMIT {
+      self.history.remove(0);
+    }
+  }
+}
+
 #[derive(Serialize, Clone, Deserialize, Type, Debug)]
 pub struct StreamStatus {
   pub is_playing: bool,
@@ -30,6 +595,142 @@ pub struct StreamStatus {
   pub path: Option<String>,
   pub volume: f32,
   pub is_muted: bool,
+  #[serde(default)]
+  pub next_path: Option<String>,
+  #[serde(default)]
+  pub crossfade: CrossfadeSettings,
+  /// Path of the track fading out while a crossfade is in progress. `path`
+  /// already points at the incoming track.
+  #[serde(default)]
+  pub crossfading_from: Option<String>,
+  #[serde(default)]
+  pub queue: QueueStatus,
+  /// How often `PlaybackPosition` is emitted while playing, in milliseconds.
+  /// `0` turns the event off.
+  #[serde(default = "default_position_interval")]
+  pub position_interval: u32,
+  #[serde(default)]
+  pub replay_gain: ReplayGainSettings,
+  /// ReplayGain offset in dB applied to the loaded track on top of `volume`.
+  #[serde(default)]
+  pub applied_gain: f32,
+  #[serde(default)]
+  pub equalizer: EqualizerSettings,
+  #[serde(default)]
+  pub eq_presets: Vec<EqPreset>,
+  /// Speed multiplier. `position` and `duration` stay in track time.
+  #[serde(default = "default_playback_rate")]
+  pub playback_rate: f64,
+  /// Keeps the original pitch when `playback_rate` isn't `1.0`.
+  #[serde(default)]
+  pub preserve_pitch: bool,
+  /// Loops this section instead of the whole track while `is_looping` is set.
+  /// Cleared when another track is loaded.
+  #[serde(default)]
+  pub loop_region: Option<LoopRegion>,
+  #[serde(default)]
+  pub output: OutputSettings,
+  /// The selected output device is missing and the default one is used until
+  /// it comes back.
+  #[serde(default)]
+  pub output_fallback: bool,
+  #[serde(default)]
+  pub sleep_timer: Option<SleepTimerStatus>,
+  #[serde(default)]
+  pub fades: TransportFades,
+  /// MiB a track may take once fully decoded for instant seeking. Longer
+  /// tracks seek through the stream instead. `0` never decodes fully.
+  #[serde(default = "default_static_memory_budget")]
+  pub static_memory_budget: u32,
+  #[serde(default)]
+  pub resume: ResumeSettings,
+  #[serde(default)]
+  pub source: Option<PlaySource>,
+  #[serde(default)]
+  pub play_count: PlayCountSettings,
+}
+
+fn default_position_interval() -> u32 {
+  250
+}
+
+fn default_playback_rate() -> f64 {
+  1.0
+}
+
+fn default_static_memory_budget() -> u32 {
+  128
+}
+
+#[derive(Serialize, Clone, Deserialize, Type, Debug, Event)]
+pub struct PlaybackPosition {
+  pub path: Option<String>,
+  pub position: f64,
+  pub duration: f64,
+}
+
+/// A listen reached the play count thresholds and the stored count went up.
+#[derive(Serialize, Clone, Deserialize, Type, Debug, Event)]
+pub struct PlayCounted {
+  pub path: String,
+  pub play_count: i32,
+}
+
+/// A track played through to its end, as opposed to being stopped or replaced.
+#[derive(Serialize, Clone, Deserialize, Type, Debug, Event)]
+pub struct TrackEnded {
+  pub path: String,
+}
+
+#[derive(Serialize, Clone, Deserialize, Type, Debug, Event)]
+pub struct PlaybackError {
+  pub path: Option<String>,
+  pub message: String,
+}
+
+#[derive(Serialize, Clone, Deserialize, Type, Debug, Event)]
+pub struct StateChanged(pub StreamStatus);
+
+/// Emitted with the whole queue whenever it changes.
+#[derive(Serialize, Clone, Deserialize, Type, Debug, Event)]
+pub struct QueueChanged(pub PlayQueue);
+
+/// The play queue as the audio thread has it.
+#[tauri::command]
+#[specta::specta]
+pub fn get_queue() -> PlayQueue {
+  let queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+  return queue.as_deref().cloned().unwrap_or_default();
+}
+
+/// Queue saved by the last session. Queues from before it was kept apart
+/// from the status are picked up from there.
+pub fn load_queue<R: Runtime>(app_handle: &AppHandle<R>) -> Option<PlayQueue> {
+  let store = app_handle.store("prefs.json").ok()?;
+
+  let saved = store.get(QUEUE_STORE_KEY).or_else(|| {
+    store
+      .get("playback-status")
+      .and_then(|status| status.get("queue").cloned())
+  })?;
+
+  return serde_json::from_value(saved).ok();
+}
+
+/// Best effort: a failed save is logged and the previous queue stays.
+pub fn save_queue<R: Runtime>(app_handle: &AppHandle<R>, queue: &PlayQueue) {
+  let store = match app_handle.store("prefs.json") {
+    Ok(store) => store,
+    Err(e) => {
+      log::warn!("failed to open prefs to save the queue: {e}");
+      return;
+    }
+  };
+
+  match serde_json::to_value(queue) {
+    Ok(value) => store.set(QUEUE_STORE_KEY, value),
+    Err(e) => log::warn!("failed to serialize the queue: {e}"),
+  }
 }
 
 #[tauri::command]
@@ -54,3 +755,164 @@ pub async fn control_playback(
 
   return Ok(response);
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn queue(tracks: &[&str], current: Option<usize>) -> PlayQueue {
+    return PlayQueue {
+      tracks: tracks.iter().map(|t| t.to_string()).collect(),
+      current,
+      ..PlayQueue::default()
+    };
+  }
+
+  #[test]
+  fn play_replaces_the_loaded_entry() {
+    let mut q = queue(&["a", "b", "c"], Some(0));
+
+    q.play("x".to_string());
+    q.play("y".to_string());
+
+    assert_eq!(q.tracks, ["y", "b", "c"]);
+    assert_eq!(q.current, Some(0));
+    assert_eq!(q.history, ["a", "x"]);
+  }
+
+  #[test]
+  fn play_moves_onto_the_upcoming_entry_when_it_matches() {
+    let mut q = queue(&["a", "b", "c"], Some(0));
+
+    q.play("b".to_string());
+
+    assert_eq!(q.tracks, ["a", "b", "c"]);
+    assert_eq!(q.current, Some(1));
+    assert_eq!(q.history, ["a"]);
+  }
+
+  #[test]
+  fn play_with_nothing_loaded_goes_in_front() {
+    let mut q = queue(&["a", "b"], None);
+
+    q.play("x".to_string());
+
+    assert_eq!(q.tracks, ["x", "a", "b"]);
+    assert_eq!(q.current, Some(0));
+    assert_eq!(q.peek_next().map(String::as_str), Some("a"));
+  }
+
+  #[test]
+  fn repeat_all_wraps_to_the_start_of_the_queue() {
+    let mut q = queue(&["a", "b", "c"], Some(0));
+    q.repeat = RepeatMode::All;
+
+    q.play("x".to_string());
+    q.jump(2);
+
+    assert_eq!(q.skip().as_deref(), Some("x"));
+    assert_eq!(q.tracks.len(), 3);
+  }
+
+  #[test]
+  fn repeat_one_holds_on_end_but_not_on_skip() {
+    let mut q = queue(&["a", "b"], Some(0));
+    q.repeat = RepeatMode::One;
+
+    assert_eq!(q.advance().as_deref(), Some("a"));
+    assert_eq!(q.skip().as_deref(), Some("b"));
+    assert_eq!(q.current, Some(1));
+  }
+
+  #[test]
+  fn skip_stops_at_the_end_without_repeat() {
+    let mut q = queue(&["a", "b"], Some(0));
+
+    assert_eq!(q.skip().as_deref(), Some("b"));
+    assert_eq!(q.skip(), None);
+    assert_eq!(q.current, Some(1));
+  }
+
+  #[test]
+  fn previous_steps_back_through_history() {
+    let mut q = queue(&["a", "b", "c"], Some(0));
+
+    q.skip();
+    q.skip();
+
+    assert_eq!(q.previous().as_deref(), Some("b"));
+    assert_eq!(q.current, Some(1));
+    assert_eq!(q.previous().as_deref(), Some("a"));
+    assert_eq!(q.current, Some(0));
+    assert_eq!(q.previous(), None);
+  }
+
+  #[test]
+  fn previous_puts_a_replaced_track_back_before_the_loaded_one() {
+    let mut q = queue(&["a", "b"], Some(0));
+
+    q.play("x".to_string());
+
+    assert_eq!(q.previous().as_deref(), Some("a"));
+    assert_eq!(q.tracks, ["a", "x", "b"]);
+    assert_eq!(q.current, Some(0));
+  }
+
+  #[test]
+  fn shuffle_keeps_played_and_loaded_entries_in_place() {
+    let tracks: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+    let mut q = PlayQueue {
+      tracks: tracks.clone(),
+      current: Some(4),
+      ..PlayQueue::default()
+    };
+
+    q.shuffle(Some(7));
+
+    assert_eq!(q.tracks[..5], tracks[..5]);
+    assert_ne!(q.tracks[5..], tracks[5..]);
+    assert_eq!(q.shuffle_seed, Some(7));
+
+    let mut sorted = q.tracks.clone();
+    sorted.sort_by_key(|t| t.parse::<u32>().ok());
+    assert_eq!(sorted, tracks);
+
+    let mut again = PlayQueue {
+      tracks: tracks.clone(),
+      current: Some(4),
+      ..PlayQueue::default()
+    };
+    again.shuffle(Some(7));
+    assert_eq!(again.tracks, q.tracks);
+  }
+
+  #[test]
+  fn sanitize_repairs_a_stale_queue() {
+    let mut q = queue(&["a", "b"], Some(5));
+    q.history = vec!["x".to_string(); QUEUE_HISTORY_LIMIT + 20];
+
+    q.sanitize();
+
+    assert_eq!(q.current, None);
+    assert_eq!(q.history.len(), QUEUE_HISTORY_LIMIT);
+    assert_eq!(q.previous().as_deref(), Some("x"));
+    assert_eq!(q.tracks, ["x", "a", "b"]);
+  }
+
+  #[test]
+  fn truncate_drops_played_entries_first() {
+    let tracks: Vec<String> = (0..QUEUE_TRACK_LIMIT).map(|i| i.to_string()).collect();
+    let mut q = PlayQueue {
+      tracks,
+      current: Some(3),
+      ..PlayQueue::default()
+    };
+
+    q.append(vec!["x".to_string(); 5]);
+
+    assert_eq!(q.tracks.len(), QUEUE_TRACK_LIMIT);
+    assert_eq!(q.current, Some(0));
+    assert_eq!(q.tracks[0], "3");
+    assert_eq!(q.tracks.last().map(String::as_str), Some("x"));
+  }
+}
@@ -0,0 +1,200 @@
+use crate::audio::IS_PLAYING;
+use crate::cover_protocol::cache_thumbnail;
+use crate::diesel_schema::library_folders;
+use crate::error::{Error, Result};
+use crate::read::collect_track_paths;
+use crate::waveform;
+use crate::waveform_cache::WaveformCache;
+use crate::DbPool;
+use diesel::{QueryDsl, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+
+// how often a paused job checks whether playback stopped
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+// breather each worker takes between files, so a long job doesn't keep the
+// cores it runs on busy the whole time
+const FILE_INTERVAL: Duration = Duration::from_millis(50);
+// waveform cache space left to tracks opened in the player. A job stops
+// short of it instead of evicting entries, its own included, to make room
+const CACHE_HEADROOM_BYTES: u64 = 64 * 1024 * 1024;
+
+static JOB: Mutex<Option<Arc<PregenJob>>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize, Type, Clone, Copy, Debug)]
+pub struct PregenSettings {
+  /// Files processed at once, one by default and never more than half the
+  /// cores, so the rest of the app stays responsive.
+  #[serde(default = "default_workers")]
+  pub workers: u32,
+  /// Hold off while audio is playing, including halfway through a file.
+  pub pause_while_playing: bool,
+}
+
+fn default_workers() -> u32 {
+  return 1;
+}
+
+/// Emitted after each file of a pre-generation job.
+#[derive(Serialize, Clone, Deserialize, Type, Debug, Event)]
+pub struct PregenProgress {
+  pub path: String,
+  pub done: u32,
+  pub total: u32,
+  /// The file couldn't be decoded and was skipped.
+  pub failed: bool,
+}
+
+struct PregenJob {
+  stop: AtomicBool,
+  pause_while_playing: bool,
+}
+
+impl PregenJob {
+  /// Blocks while the job is paused. Returns `false` once it is stopped.
+  fn wait_while_paused(&self) -> bool {
+    loop {
+      if self.stop.load(Ordering::Relaxed) {
+        return false;
+      }
+      if !self.pause_while_playing || !IS_PLAYING.load(Ordering::Relaxed) {
+        return true;
+      }
+
+      thread::sleep(PAUSE_POLL_INTERVAL);
+    }
+  }
+}
+
+/// Fills the waveform and thumbnail caches for every track in the library
+/// folders in the background, emitting `PregenProgress` as it goes. Tracks
+/// already cached are skipped, and the job stops once the waveform cache is
+/// close to full. A new job replaces the one running.
+#[tauri::command]
+#[specta::specta]
+pub fn start_pregen(app_handle: AppHandle<tauri::Wry>, settings: PregenSettings) -> Result<()> {
+  let mut conn = app_handle
+    .state::<DbPool>()
+    .get()
+    .map_err(|e| Error::Sql(e.to_string()))?;
+
+  let folders = library_folders::table
+    .select((library_folders::path, library_folders::recursive))
+    .load::<(String, bool)>(&mut conn)
+    .map_err(|e| Error::Sql(e.to_string()))?;
+
+  let cache = WaveformCache::new(&app_handle)?;
+  let cache_dir = app_handle
+    .path()
+    .app_cache_dir()
+    .map_err(|_| Error::FileSystem("failed to get cache directory".to_string()))?;
+
+  let cores = thread::available_parallelism().map_or(1, |n| n.get());
+  let workers = (settings.workers as usize).clamp(1, (cores / 2).max(1));
+
+  let job = Arc::new(PregenJob {
+    stop: AtomicBool::new(false),
+    pause_while_playing: settings.pause_while_playing,
+  });
+  if let Some(previous) = replace_job(Some(job.clone())) {
+    previous.stop.store(true, Ordering::Relaxed);
+  }
+
+  thread::Builder::new()
+    .name("pregen".to_string())
+    .spawn(move || {
+      run(&app_handle, &job, folders, &cache, &cache_dir, workers);
+
+      // only the latest job may clear itself
+      let mut current = JOB.lock().unwrap_or_else(|e| e.into_inner());
+      if current.as_ref().is_some_and(|j| Arc::ptr_eq(j, &job)) {
+        *current = None;
+      }
+    })
+    .map_err(|e| Error::Other(format!("failed to start pre-generation: {}", e)))?;
+
+  return Ok(());
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn stop_pregen() {
+  if let Some(job) = replace_job(None) {
+    job.stop.store(true, Ordering::Relaxed);
+  }
+}
+
+fn replace_job(next: Option<Arc<PregenJob>>) -> Option<Arc<PregenJob>> {
+  let mut job = JOB.lock().unwrap_or_else(|e| e.into_inner());
+  return std::mem::replace(&mut *job, next);
+}
+
+fn run(
+  app_handle: &AppHandle<tauri::Wry>,
+  job: &PregenJob,
+  folders: Vec<(String, bool)>,
+  cache: &WaveformCache,
+  cache_dir: &Path,
+  workers: usize,
+) {
+  let mut seen = HashSet::new();
+  let mut paths = Vec::new();
+  for (folder, recursive) in folders {
+    match collect_track_paths(&folder, recursive) {
+      Ok(found) => paths.extend(found.into_iter().filter(|path| seen.insert(path.clone()))),
+      Err(e) => log::warn!("failed to list library folder {}: {}", folder, e),
+    }
+  }
+
+  let total = paths.len() as u32;
+  let next = AtomicUsize::new(0);
+  let done = AtomicU32::new(0);
+
+  thread::scope(|scope| {
+    for _ in 0..workers {
+      scope.spawn(|| {
+        while job.wait_while_paused() {
+          if cache.room() < CACHE_HEADROOM_BYTES {
+            log::info!("waveform cache is full, stopping pre-generation");
+            break;
+          }
+
+          let Some(path) = paths.get(next.fetch_add(1, Ordering::Relaxed)) else {
+            break;
+          };
+
+          let result = waveform::pregenerate(cache, path, || job.wait_while_paused());
+          if job.stop.load(Ordering::Relaxed) {
+            break;
+          }
+          if let Err(e) = &result {
+            log::warn!("failed to pre-generate waveform for {}: {}", path, e);
+          }
+          if let Err(e) = cache_thumbnail(path, cache_dir) {
+            log::warn!("failed to pre-generate thumbnail for {}: {}", path, e);
+          }
+
+          let progress = PregenProgress {
+            path: path.clone(),
+            done: done.fetch_add(1, Ordering::Relaxed) + 1,
+            total,
+            failed: result.is_err(),
+          };
+          if let Err(e) = progress.emit(app_handle) {
+            log::error!("failed to emit pre-generation progress: {e}");
+          }
+
+          thread::sleep(FILE_INTERVAL);
+        }
+      });
+    }
+  });
+}
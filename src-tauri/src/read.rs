@@ -2,6 +2,7 @@ use crate::diesel_schema::track_play_count::dsl::*;
 use crate::error::Error;
 use crate::error::Result;
 use crate::id3::TagTypeArg;
+use crate::tags::read_tags;
 use crate::utils::get_track_identity_key;
 use crate::DbPool;
 use dashmap::DashMap;
@@ -13,7 +14,8 @@ use diesel::RunQueryDsl;
 use id3::v1v2::read_from_path;
 use id3::ErrorKind;
 use id3::Tag;
-use serde::Serialize;
+use id3::TagLike;
+use serde::{Deserialize, Serialize};
 use specta::Type;
 use std::collections::HashMap;
 use std::fs::read_dir;
@@ -47,6 +49,39 @@ pub struct FileEntry {
   pub play_count: i32,
 }
 
+/// ReplayGain values in dB (gain) and linear amplitude (peak).
+#[derive(Serialize, Deserialize, Type, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReplayGain {
+  pub track_gain: Option<f32>,
+  pub track_peak: Option<f32>,
+  pub album_gain: Option<f32>,
+  pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+  fn is_empty(&self) -> bool {
+    return *self == ReplayGain::default();
+  }
+
+  fn set(&mut self, key: &str, value: &str) {
+    let key = key.to_ascii_uppercase();
+    let Some(value) = parse_replay_gain_value(value) else {
+      return;
+    };
+
+    // suffix match covers TXXX descriptions, vorbis comments and mp4 freeform atoms
+    if key.ends_with("REPLAYGAIN_TRACK_GAIN") {
+      self.track_gain = Some(value);
+    } else if key.ends_with("REPLAYGAIN_TRACK_PEAK") {
+      self.track_peak = Some(value);
+    } else if key.ends_with("REPLAYGAIN_ALBUM_GAIN") {
+      self.album_gain = Some(value);
+    } else if key.ends_with("REPLAYGAIN_ALBUM_PEAK") {
+      self.album_peak = Some(value);
+    }
+  }
+}
+
 pub static FOLDER_CACHE: LazyLock<DashMap<String, Arc<Vec<String>>>> = LazyLock::new(DashMap::new);
 pub static TRACK_CACHE: LazyLock<DashMap<String, FileEntry>> = LazyLock::new(DashMap::new);
 
@@ -93,21 +128,16 @@ pub async fn read_folder(
 #[tauri::command]
 #[specta::specta]
 pub async fn get_folder_track_paths(path: String, deep: Option<bool>) -> Result<Vec<String>> {
-  spawn_blocking(move || {
-    if !deep.unwrap_or(false) {
-      let paths = read_dir(&path)
-        .map_err(|e| Error::FileSystem(e.to_string()))?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.path().is_file())
-        .filter(|entry| is_supported(entry.path()))
-        .map(|entry| entry.path().to_string_lossy().to_string())
-        .collect::<Vec<String>>();
-
-      return Ok(paths);
-    }
+  spawn_blocking(move || collect_track_paths(&path, deep.unwrap_or(false)))
+    .await
+    .map_err(|e| Error::FileSystem(e.to_string()))?
+}
 
-    let paths = jwalk::WalkDir::new(&path)
-      .into_iter()
+/// Supported files in a folder, including subfolders with `deep`.
+pub fn collect_track_paths(path: &str, deep: bool) -> Result<Vec<String>> {
+  if !deep {
+    let paths = read_dir(path)
+      .map_err(|e| Error::FileSystem(e.to_string()))?
       .filter_map(|entry| entry.ok())
       .filter(|entry| entry.path().is_file())
       .filter(|entry| is_supported(entry.path()))
@@ -115,9 +145,17 @@ pub async fn get_folder_track_paths(path: String, deep: Option<bool>) -> Result<
       .collect::<Vec<String>>();
 
     return Ok(paths);
-  })
-  .await
-  .map_err(|e| Error::FileSystem(e.to_string()))?
+  }
+
+  let paths = jwalk::WalkDir::new(path)
+    .into_iter()
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| entry.path().is_file())
+    .filter(|entry| is_supported(entry.path()))
+    .map(|entry| entry.path().to_string_lossy().to_string())
+    .collect::<Vec<String>>();
+
+  return Ok(paths);
 }
 
 #[tauri::command]
@@ -161,6 +199,24 @@ pub async fn get_track_data(
     .map_err(|e| Error::FileSystem(e.to_string()))?
 }
 
+/// Drops the cached entry of `path`, so its tags are read again.
+pub fn invalidate_track(path: &str) {
+  TRACK_CACHE.remove(path);
+}
+
+/// Drops the cached entries of every file sharing an identity key, so their
+/// play count is read again.
+pub fn invalidate_track_identity(key: &str) {
+  TRACK_CACHE.retain(|_, entry| {
+    let entry_key = get_track_identity_key(
+      entry.tags.get("TIT2").map(String::as_str),
+      entry.tags.get("TPE1").map(String::as_str),
+    );
+
+    return entry_key.as_deref() != Some(key);
+  });
+}
+
 fn get_track_data_core(
   app_handle: AppHandle<tauri::Wry>,
   path_string: String,
@@ -204,8 +260,7 @@ fn file_entry_from_path(app_handle: AppHandle<tauri::Wry>, path: PathBuf) -> Res
     });
   }
 
-  let primary_tag = get_primary_tag(&path)?;
-  let tag_map = get_tag_map(primary_tag.clone())?;
+  let (tag_map, primary_tag) = read_tag_map(&path)?;
   let full_uri = build_cover_uri(path.to_string_lossy().as_ref(), "full");
   let thumbnail_uri = build_cover_uri(path.to_string_lossy().as_ref(), "thumbnail");
   let duration = get_duration(&path)?;
@@ -232,13 +287,27 @@ fn file_entry_from_path(app_handle: AppHandle<tauri::Wry>, path: PathBuf) -> Res
     name,
     is_playlist_track: false,
     valid: true,
-    primary_tag: get_primary_tag_version(primary_tag),
+    primary_tag,
     extension,
     duration,
     play_count: play_count_res.unwrap_or(-1),
   });
 }
 
+/// Tags keyed by ID3v2 frame ID, from the ID3 tag when the file has one and
+/// from the format's own tag otherwise.
+fn read_tag_map(path: &Path) -> Result<(SerializableTagMap, Option<TagTypeArg>)> {
+  if let Some(tag) = get_primary_tag(path)? {
+    let version = get_primary_tag_version(Some(tag.clone()));
+    return Ok((get_tag_map(Some(tag))?, version));
+  }
+
+  return Ok(match read_tags(path)? {
+    Some((tag_map, tag_type)) => (tag_map, Some(tag_type)),
+    None => (SerializableTagMap::new(), None),
+  });
+}
+
 fn get_tag_map(tag: Option<Tag>) -> Result<SerializableTagMap> {
   return match tag {
     Some(tag) => {
@@ -323,6 +392,66 @@ fn get_primary_tag_version(tag: Option<Tag>) -> Option<TagTypeArg> {
   };
 }
 
+/// Reads ReplayGain values from ID3 `TXXX` frames, falling back to the tag
+/// lofty reads for other containers (vorbis comments, mp4, ape).
+pub fn get_replay_gain(path: impl AsRef<Path>) -> Result<ReplayGain> {
+  let path = path.as_ref();
+  let mut replay_gain = ReplayGain::default();
+
+  // a broken id3 tag shouldn't hide vorbis/mp4 tags further down
+  if let Ok(Some(tag)) = get_primary_tag(path) {
+    for text in tag.extended_texts() {
+      replay_gain.set(&text.description, &text.value);
+    }
+  }
+
+  if !replay_gain.is_empty() {
+    return Ok(replay_gain);
+  }
+
+  if let Some((tag_map, _)) = read_tags(path)? {
+    for (key, value) in &tag_map {
+      replay_gain.set(key, value);
+    }
+  }
+
+  return Ok(replay_gain);
+}
+
+/// Key that is equal for tracks of the same album: the album tag when there
+/// is one, the containing folder otherwise.
+pub fn get_album_key(path: impl AsRef<Path>) -> Option<String> {
+  let path = path.as_ref();
+
+  if let Ok((tag_map, _)) = read_tag_map(path) {
+    if let Some(album) = tag_map.get("TALB").filter(|album| !album.trim().is_empty()) {
+      return Some(format!("album:{}", album.trim()));
+    }
+  }
+
+  return path
+    .parent()
+    .map(|parent| format!("folder:{}", parent.to_string_lossy()));
+}
+
+/// Identity key from the title and artist tags, as used for play counts.
+pub fn get_track_identity(path: impl AsRef<Path>) -> Option<String> {
+  let (tag_map, _) = read_tag_map(path.as_ref()).ok()?;
+
+  return get_track_identity_key(
+    tag_map.get("TIT2").map(String::as_str),
+    tag_map.get("TPE1").map(String::as_str),
+  );
+}
+
+/// Parses values like `-6.48 dB` or `0.988553`.
+fn parse_replay_gain_value(value: &str) -> Option<f32> {
+  let number = value.split_whitespace().next()?;
+  let number = number.strip_suffix("dB").unwrap_or(number);
+
+  return number.parse::<f32>().ok().filter(|v| v.is_finite());
+}
+
 fn get_duration(path: impl AsRef<Path>) -> Result<f64> {
   let path = path.as_ref();
   let file = File::open(path).map_err(|e| Error::FileSystem(e.to_string()))?;
@@ -0,0 +1,107 @@
+use crate::diesel_schema::resume_positions;
+use crate::utils::FileSignature;
+use crate::DbPool;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::{
+  ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, RunQueryDsl, Selectable,
+  SelectableHelper, SqliteConnection,
+};
+use tauri::{AppHandle, Manager};
+
+// positions this close to either end aren't worth coming back to
+const MIN_RESUME_POSITION: f64 = 10.0;
+const END_MARGIN: f64 = 30.0;
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = resume_positions)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct ResumePositionRow {
+  path: String,
+  file_size: i64,
+  modified_at: i64,
+  position: f64,
+}
+
+/// Last positions of long tracks, keyed by path. A position only applies while
+/// the file keeps its size and modification time. Best effort like the
+/// loudness cache: failures are logged and the track starts from the top.
+#[derive(Clone)]
+pub struct ResumeStore {
+  pool: Option<DbPool>,
+}
+
+impl ResumeStore {
+  pub fn new(app_handle: &AppHandle<tauri::Wry>) -> Self {
+    let pool = app_handle
+      .try_state::<DbPool>()
+      .map(|pool| pool.inner().clone());
+
+    return Self { pool };
+  }
+
+  fn conn(&self) -> Option<PooledConnection<ConnectionManager<SqliteConnection>>> {
+    return self
+      .pool
+      .as_ref()?
+      .get()
+      .inspect_err(|e| log::warn!("resume positions unavailable: {e}"))
+      .ok();
+  }
+
+  pub fn load(&self, path: &str) -> Option<f64> {
+    let signature = FileSignature::read(path).ok()?;
+    let mut conn = self.conn()?;
+
+    let row = resume_positions::table
+      .find(path)
+      .filter(resume_positions::file_size.eq(signature.size))
+      .filter(resume_positions::modified_at.eq(signature.modified_at))
+      .select(ResumePositionRow::as_select())
+      .first(&mut conn)
+      .optional()
+      .inspect_err(|e| log::warn!("failed to read resume position: {e}"))
+      .ok()??;
+
+    return Some(row.position);
+  }
+
+  /// Stores `position`, or forgets the track when it is too close to the start
+  /// or end to be worth resuming.
+  pub fn save(&self, path: &str, position: f64, duration: f64) {
+    if position < MIN_RESUME_POSITION || position > duration - END_MARGIN {
+      self.clear(path);
+      return;
+    }
+
+    let Ok(signature) = FileSignature::read(path) else {
+      return;
+    };
+    let Some(mut conn) = self.conn() else {
+      return;
+    };
+
+    let row = ResumePositionRow {
+      path: path.to_string(),
+      file_size: signature.size,
+      modified_at: signature.modified_at,
+      position,
+    };
+
+    if let Err(e) = diesel::replace_into(resume_positions::table)
+      .values(&row)
+      .execute(&mut conn)
+    {
+      log::warn!("failed to save resume position: {e}");
+    }
+  }
+
+  pub fn clear(&self, path: &str) {
+    let Some(mut conn) = self.conn() else {
+      return;
+    };
+
+    if let Err(e) = diesel::delete(resume_positions::table.find(path)).execute(&mut conn) {
+      log::warn!("failed to clear resume position: {e}");
+    }
+  }
+}
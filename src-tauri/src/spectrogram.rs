@@ -0,0 +1,350 @@
+use crate::decoder::{decode_interleaved, GaplessDecoder};
+use crate::error::{Error, Result};
+use crate::waveform_cache::WaveformCache;
+use image::{ImageFormat, Rgb, RgbImage};
+use kira::sound::streaming::Decoder;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use std::f32::consts::PI;
+use std::io::Cursor;
+use std::sync::Arc;
+use tauri::http::header::CONTENT_TYPE;
+use tauri::http::{HeaderValue, Request, Response, StatusCode};
+use tauri::{async_runtime::spawn_blocking, AppHandle, UriSchemeContext, UriSchemeResponder, Wry};
+
+const MIN_FFT_SIZE: u32 = 256;
+const MAX_FFT_SIZE: u32 = 32768;
+const MAX_IMAGE_SIDE: u32 = 8192;
+// lowest frequency drawn on a log axis
+const LOG_MIN_FREQUENCY: f32 = 20.0;
+
+#[derive(Serialize, Deserialize, Type, Clone, Copy, Debug, PartialEq)]
+pub enum SpectrogramWindow {
+  Hann,
+  Hamming,
+  Blackman,
+  Rectangular,
+}
+
+#[derive(Serialize, Deserialize, Type, Clone, Copy, Debug, PartialEq)]
+pub enum ColorMap {
+  Grayscale,
+  Inferno,
+  Viridis,
+}
+
+#[derive(Serialize, Deserialize, Type, Clone, Copy, Debug, PartialEq)]
+pub enum FrequencyScale {
+  Linear,
+  Log,
+}
+
+#[derive(Serialize, Deserialize, Type, Clone, Copy, Debug, PartialEq)]
+pub struct SpectrogramSettings {
+  pub window: SpectrogramWindow,
+  /// Rounded to a power of two. Larger sizes resolve frequency more finely.
+  pub fft_size: u32,
+  /// Levels at or below `min_db` dBFS are drawn as the darkest colour.
+  pub min_db: f32,
+  pub max_db: f32,
+  pub color_map: ColorMap,
+  pub frequency_scale: FrequencyScale,
+  /// One column per window, spread evenly over the track.
+  pub width: u32,
+  pub height: u32,
+}
+
+/// Renders a spectrogram of `path` to PNG and returns a `spectrogram://` URI
+/// for it. Images are cached per file state and settings.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_spectrogram(
+  app_handle: AppHandle<tauri::Wry>,
+  path: String,
+  settings: SpectrogramSettings,
+) -> Result<String> {
+  spawn_blocking(move || {
+    let cache = WaveformCache::spectrograms(&app_handle)?;
+    let key = build_cache_key(&path, &settings)?;
+
+    if !cache.contains(&key) {
+      let image = render(&path, &settings)?;
+      let mut png = Vec::new();
+      image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| Error::Waveform(format!("failed to encode spectrogram: {}", e)))?;
+
+      // the image is only served from the cache
+      cache.write(&key, &png);
+      if !cache.contains(&key) {
+        return Err(Error::Waveform("failed to write spectrogram".to_string()));
+      }
+    }
+
+    return Ok(format!("spectrogram://localhost/{}", key));
+  })
+  .await
+  .map_err(|e| Error::Waveform(e.to_string()))?
+}
+
+/// Serves images rendered by `get_spectrogram` from the spectrogram cache.
+pub fn handler(ctx: UriSchemeContext<Wry>, req: Request<Vec<u8>>, responder: UriSchemeResponder) {
+  let cache = WaveformCache::spectrograms(ctx.app_handle());
+
+  tokio::task::spawn_blocking(move || {
+    let key = req.uri().path().trim_start_matches('/');
+
+    // keys are md5 hex digests, anything else could point outside the cache
+    let image = match cache {
+      Ok(cache) if !key.is_empty() && key.chars().all(|c| c.is_ascii_hexdigit()) => cache.read(key),
+      _ => None,
+    };
+
+    // set up directly rather than through the builder, which only exists to
+    // report invalid headers
+    let response = match image {
+      Some(image) => {
+        let mut response = Response::new(image);
+        response
+          .headers_mut()
+          .insert(CONTENT_TYPE, HeaderValue::from_static("image/png"));
+        response
+      }
+      None => {
+        let mut response = Response::new(b"Not found".to_vec());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        response
+      }
+    };
+
+    responder.respond(response);
+  });
+}
+
+fn build_cache_key(path: &str, settings: &SpectrogramSettings) -> Result<String> {
+  let settings = serde_json::to_string(settings)
+    .map_err(|e| Error::Waveform(format!("failed to serialize settings: {}", e)))?;
+
+  return WaveformCache::key(path, &settings);
+}
+
+fn render(path: &str, settings: &SpectrogramSettings) -> Result<RgbImage> {
+  let fft_size = settings
+    .fft_size
+    .clamp(MIN_FFT_SIZE, MAX_FFT_SIZE)
+    .next_power_of_two() as usize;
+  let width = settings.width.clamp(1, MAX_IMAGE_SIDE);
+  let height = settings.height.clamp(1, MAX_IMAGE_SIDE);
+
+  let num_frames = GaplessDecoder::from_file(path)
+    .map_err(|e| Error::Audio(format!("failed to open {}: {}", path, e)))?
+    .num_frames();
+
+  let mut image = RgbImage::new(width, height);
+  let mut stft = Stft::new(fft_size, settings.window, num_frames, width as usize);
+  let mut mono = Vec::new();
+  let mut sample_rate = 0;
+
+  decode_interleaved(path, |spec, samples| {
+    sample_rate = spec.rate;
+
+    let channels = spec.channels.count();
+    mono.clear();
+    mono.extend(
+      samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+    );
+
+    stft.push(&mono, |x, bins| {
+      draw_column(&mut image, x, bins, sample_rate, settings);
+    });
+
+    return Ok(());
+  })?;
+
+  stft.finish(|x, bins| {
+    draw_column(&mut image, x, bins, sample_rate, settings);
+  });
+
+  return Ok(image);
+}
+
+/// Short-time Fourier transform producing a fixed number of columns, with
+/// windows placed evenly over the track however long it is. Columns are
+/// magnitudes in dBFS, one per bin up to Nyquist.
+pub struct Stft {
+  fft: Arc<dyn Fft<f32>>,
+  window: Vec<f32>,
+  scale: f32,
+  buffer: Vec<Complex<f32>>,
+  scratch: Vec<Complex<f32>>,
+  magnitudes: Vec<f32>,
+  /// Frames between window starts.
+  hop: f64,
+  columns: usize,
+  next_column: usize,
+  /// Decoded samples not yet needed by any remaining window, starting at
+  /// frame `pending_start`.
+  pending: Vec<f32>,
+  pending_start: usize,
+}
+
+impl Stft {
+  pub fn new(size: usize, window: SpectrogramWindow, num_frames: usize, columns: usize) -> Self {
+    let fft = FftPlanner::new().plan_fft_forward(size);
+    let window: Vec<f32> = (0..size).map(|i| window_value(window, i, size)).collect();
+    let scale = 2.0 / window.iter().sum::<f32>().max(f32::EPSILON);
+
+    return Self {
+      scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+      fft,
+      window,
+      scale,
+      buffer: vec![Complex::default(); size],
+      magnitudes: vec![0.0; size / 2],
+      hop: num_frames.saturating_sub(size) as f64 / columns as f64,
+      columns,
+      next_column: 0,
+      pending: Vec::new(),
+      pending_start: 0,
+    };
+  }
+
+  fn column_start(&self, column: usize) -> usize {
+    return (column as f64 * self.hop) as usize;
+  }
+
+  pub fn push(&mut self, samples: &[f32], mut on_column: impl FnMut(u32, &[f32])) {
+    self.pending.extend_from_slice(samples);
+
+    while self.next_column < self.columns {
+      let start = self.column_start(self.next_column) - self.pending_start;
+      if start + self.window.len() > self.pending.len() {
+        break;
+      }
+
+      self.analyze(start);
+      on_column(self.next_column as u32, &self.magnitudes);
+      self.next_column += 1;
+
+      let keep_from =
+        (self.column_start(self.next_column) - self.pending_start).min(self.pending.len());
+      self.pending.drain(..keep_from);
+      self.pending_start += keep_from;
+    }
+  }
+
+  /// Fills the remaining columns, padding windows that run past the end.
+  fn finish(&mut self, mut on_column: impl FnMut(u32, &[f32])) {
+    let padding = vec![0.0; self.window.len()];
+    self.push(&padding, &mut on_column);
+
+    while self.next_column < self.columns {
+      on_column(self.next_column as u32, &self.magnitudes);
+      self.next_column += 1;
+    }
+  }
+
+  fn analyze(&mut self, start: usize) {
+    let samples = &self.pending[start..start + self.window.len()];
+    for ((bin, sample), window) in self.buffer.iter_mut().zip(samples).zip(&self.window) {
+      *bin = Complex::new(sample * window, 0.0);
+    }
+
+    self
+      .fft
+      .process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+    for (magnitude, bin) in self.magnitudes.iter_mut().zip(&self.buffer) {
+      *magnitude = 20.0 * (bin.norm() * self.scale).max(1e-12).log10();
+    }
+  }
+}
+
+fn window_value(window: SpectrogramWindow, i: usize, size: usize) -> f32 {
+  let phase = 2.0 * PI * i as f32 / size as f32;
+
+  return match window {
+    SpectrogramWindow::Hann => 0.5 - 0.5 * phase.cos(),
+    SpectrogramWindow::Hamming => 0.54 - 0.46 * phase.cos(),
+    SpectrogramWindow::Blackman => 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos(),
+    SpectrogramWindow::Rectangular => 1.0,
+  };
+}
+
+/// Draws one column, high frequencies at the top. Each pixel shows the
+/// loudest bin it covers so narrow cutoffs stay visible when scaled down.
+fn draw_column(
+  image: &mut RgbImage,
+  x: u32,
+  bins: &[f32],
+  sample_rate: u32,
+  settings: &SpectrogramSettings,
+) {
+  let height = image.height();
+  let nyquist = sample_rate as f32 / 2.0;
+  let bin_width = nyquist / bins.len() as f32;
+  let db_range = (settings.max_db - settings.min_db).max(f32::EPSILON);
+
+  let frequency_at = |row: f32| -> f32 {
+    let t = row / height as f32;
+    return match settings.frequency_scale {
+      FrequencyScale::Linear => t * nyquist,
+      FrequencyScale::Log => {
+        let min = LOG_MIN_FREQUENCY.min(nyquist);
+        min * (nyquist / min).powf(t)
+      }
+    };
+  };
+
+  for row in 0..height {
+    let low = (frequency_at(row as f32) / bin_width) as usize;
+    let high = ((frequency_at(row as f32 + 1.0) / bin_width) as usize).max(low + 1);
+
+    let level = bins[low.min(bins.len() - 1)..high.min(bins.len())]
+      .iter()
+      .copied()
+      .fold(f32::NEG_INFINITY, f32::max);
+    let t = ((level - settings.min_db) / db_range).clamp(0.0, 1.0);
+
+    image.put_pixel(x, height - 1 - row, color(settings.color_map, t));
+  }
+}
+
+fn color(map: ColorMap, t: f32) -> Rgb<u8> {
+  let stops: &[[u8; 3]] = match map {
+    ColorMap::Grayscale => return Rgb([(t * 255.0) as u8; 3]),
+    ColorMap::Inferno => &[
+      [0, 0, 4],
+      [40, 11, 84],
+      [101, 21, 110],
+      [159, 42, 99],
+      [212, 72, 66],
+      [245, 125, 21],
+      [250, 193, 39],
+      [252, 255, 164],
+    ],
+    ColorMap::Viridis => &[
+      [68, 1, 84],
+      [70, 50, 126],
+      [54, 92, 141],
+      [39, 127, 142],
+      [31, 161, 135],
+      [74, 193, 109],
+      [160, 218, 57],
+      [253, 231, 37],
+    ],
+  };
+
+  let position = t * (stops.len() - 1) as f32;
+  let index = (position as usize).min(stops.len() - 2);
+  let fraction = position - index as f32;
+
+  let [a, b] = [stops[index], stops[index + 1]];
+  return Rgb(std::array::from_fn(|channel| {
+    (a[channel] as f32 + (b[channel] as f32 - a[channel] as f32) * fraction).round() as u8
+  }));
+}
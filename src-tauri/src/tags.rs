@@ -0,0 +1,516 @@
+use crate::error::{Error, Result};
+use crate::id3::{can_write_id3, write_frames, FrameArgs, TagTypeArg};
+use crate::read::{invalidate_track, SerializableTagMap};
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::error::{ErrorKind, LoftyError};
+use lofty::file::{AudioFile, FileType, TaggedFileExt};
+use lofty::flac::FlacFile;
+use lofty::mp4::{Atom, AtomData, AtomIdent, Mp4File};
+use lofty::ogg::{OpusFile, SpeexFile, VorbisComments, VorbisFile};
+use lofty::probe::Probe;
+use lofty::tag::{Accessor, ItemKey, ItemValue, Tag, TagExt, TagType};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek};
+use std::path::Path;
+use tauri::async_runtime::spawn_blocking;
+
+// ID3v2 frame, Vorbis comment and MP4 atom of each field written. Fields
+// without a standard atom go into an iTunes freeform atom named after the
+// Vorbis comment. Track and disc numbers are handled separately.
+const FIELDS: &[(&str, &str, Option<&[u8; 4]>)] = &[
+  ("TIT2", "TITLE", Some(b"\xa9nam")),
+  ("TIT3", "SUBTITLE", None),
+  ("TPE1", "ARTIST", Some(b"\xa9ART")),
+  ("TALB", "ALBUM", Some(b"\xa9alb")),
+  ("TPE2", "ALBUMARTIST", Some(b"aART")),
+  ("TPE3", "CONDUCTOR", None),
+  ("TPE4", "REMIXER", None),
+  ("TCOM", "COMPOSER", Some(b"\xa9wrt")),
+  ("TEXT", "LYRICIST", None),
+  ("TPUB", "LABEL", None),
+  ("TCOP", "COPYRIGHT", Some(b"cprt")),
+  ("TCON", "GENRE", Some(b"\xa9gen")),
+  ("TMOO", "MOOD", None),
+  ("COMM", "COMMENT", Some(b"\xa9cmt")),
+  ("USLT", "LYRICS", Some(b"\xa9lyr")),
+  ("TBPM", "BPM", None),
+  ("TSRC", "ISRC", None),
+  ("TENC", "ENCODEDBY", None),
+  ("TSSE", "ENCODER", Some(b"\xa9too")),
+  ("TDRC", "DATE", Some(b"\xa9day")),
+  ("TDOR", "ORIGINALDATE", None),
+];
+
+const ITUNES_MEAN: &str = "com.apple.iTunes";
+
+impl From<TagType> for TagTypeArg {
+  fn from(tag_type: TagType) -> Self {
+    match tag_type {
+      TagType::Ape => TagTypeArg::Ape,
+      TagType::Id3v1 => TagTypeArg::Id3v1,
+      TagType::Mp4Ilst => TagTypeArg::Mp4Ilst,
+      TagType::VorbisComments => TagTypeArg::VorbisComments,
+      TagType::RiffInfo => TagTypeArg::RiffInfo,
+      TagType::AiffText => TagTypeArg::AiffText,
+      // lofty doesn't say which version, and ID3v2 is read by the id3 crate first
+      _ => TagTypeArg::Id3v24,
+    }
+  }
+}
+
+/// Reads the primary tag of formats without ID3v2 (Vorbis comments, FLAC,
+/// MP4 ilst, APEv2, RIFF INFO, AIFF text chunks). Fields are keyed by their
+/// ID3v2.4 frame IDs, like the tags of MP3s, so the rest of the app doesn't
+/// need to know the format. Returns `None` for files without a tag or in a
+/// format lofty can't read.
+pub fn read_tags(path: &Path) -> Result<Option<(SerializableTagMap, TagTypeArg)>> {
+  let tagged_file = Probe::open(path)
+    .map_err(|e| Error::FileSystem(e.to_string()))?
+    .options(ParseOptions::new().read_properties(false))
+    .read();
+
+  let tagged_file = match tagged_file {
+    Ok(tagged_file) => tagged_file,
+    Err(e) if matches!(e.kind(), ErrorKind::UnknownFormat) => return Ok(None),
+    Err(e) => return Err(Error::Tag(e.to_string())),
+  };
+
+  let Some(tag) = tagged_file.primary_tag().or(tagged_file.first_tag()) else {
+    return Ok(None);
+  };
+
+  return Ok(Some((get_tag_map(tag), tag.tag_type().into())));
+}
+
+fn get_tag_map(tag: &Tag) -> SerializableTagMap {
+  let mut tag_map = SerializableTagMap::new();
+  let mut track = (None, None);
+  let mut disc = (None, None);
+
+  for item in tag.items() {
+    let value = match item.value() {
+      ItemValue::Text(value) | ItemValue::Locator(value) => value.clone(),
+      _ => continue,
+    };
+
+    match item.key() {
+      ItemKey::TrackNumber => track.0 = Some(value),
+      ItemKey::TrackTotal => track.1 = Some(value),
+      ItemKey::DiscNumber => disc.0 = Some(value),
+      ItemKey::DiscTotal => disc.1 = Some(value),
+      // release year only, when there is no full date
+      ItemKey::Year => {
+        tag_map.entry("TDRC".to_string()).or_insert(value);
+      }
+      ItemKey::Unknown(key) => {
+        tag_map.insert(key.to_ascii_uppercase(), value);
+      }
+      key => {
+        if let Some(frame) = get_frame_id(key) {
+          tag_map.insert(frame.to_string(), value);
+        }
+      }
+    }
+  }
+
+  // ID3v2 keeps the total in the same frame, as `number/total`
+  for (frame, (number, total)) in [("TRCK", track), ("TPOS", disc)] {
+    let value = match (number, total) {
+      (Some(number), Some(total)) => format!("{}/{}", number, total),
+      (Some(number), None) => number,
+      _ => continue,
+    };
+    tag_map.insert(frame.to_string(), value);
+  }
+
+  return tag_map;
+}
+
+fn get_frame_id(key: &ItemKey) -> Option<&'static str> {
+  return Some(match key {
+    ItemKey::TrackTitle => "TIT2",
+    ItemKey::TrackSubtitle => "TIT3",
+    ItemKey::TrackArtist => "TPE1",
+    ItemKey::AlbumTitle => "TALB",
+    ItemKey::AlbumArtist => "TPE2",
+    ItemKey::Conductor => "TPE3",
+    ItemKey::Remixer => "TPE4",
+    ItemKey::Composer => "TCOM",
+    ItemKey::Lyricist => "TEXT",
+    ItemKey::Label | ItemKey::Publisher => "TPUB",
+    ItemKey::CopyrightMessage => "TCOP",
+    ItemKey::Genre => "TCON",
+    ItemKey::Mood => "TMOO",
+    ItemKey::Comment => "COMM",
+    ItemKey::Lyrics => "USLT",
+    ItemKey::Bpm | ItemKey::IntegerBpm => "TBPM",
+    ItemKey::Isrc => "TSRC",
+    ItemKey::EncodedBy => "TENC",
+    ItemKey::EncoderSoftware => "TSSE",
+    ItemKey::RecordingDate => "TDRC",
+    ItemKey::OriginalReleaseDate => "TDOR",
+    // no frame of their own, ID3v2 keeps them in TXXX under these names
+    ItemKey::ReplayGainTrackGain => "REPLAYGAIN_TRACK_GAIN",
+    ItemKey::ReplayGainTrackPeak => "REPLAYGAIN_TRACK_PEAK",
+    ItemKey::ReplayGainAlbumGain => "REPLAYGAIN_ALBUM_GAIN",
+    ItemKey::ReplayGainAlbumPeak => "REPLAYGAIN_ALBUM_PEAK",
+    _ => return None,
+  });
+}
+
+/// Writes `args` to the file's `target_tag`, with the frame IDs `FileEntry.tags`
+/// uses. ID3v2 frames are written as they are, Vorbis comments and MP4 atoms
+/// are mapped from them and anything unmapped is written under its own name.
+/// Empty values remove the field. Only the edited tag is rewritten, so other
+/// metadata, embedded pictures included, stays as it was.
+#[tauri::command]
+#[specta::specta]
+pub async fn write_tags(
+  file_path: String,
+  target_tag: TagTypeArg,
+  args: Vec<FrameArgs>,
+) -> Result<()> {
+  return spawn_blocking(move || {
+    let written = match target_tag {
+      TagTypeArg::Id3v22 | TagTypeArg::Id3v23 | TagTypeArg::Id3v24 => {
+        if !can_write_id3(&file_path) {
+          return Err(Error::Tag(format!("{} can't hold an ID3v2 tag", file_path)));
+        }
+        write_frames(&file_path, target_tag, args)
+      }
+      TagTypeArg::VorbisComments => write_vorbis_comments(&file_path, &args),
+      TagTypeArg::Mp4Ilst => write_ilst(&file_path, &args),
+      other => Err(Error::Tag(format!(
+        "writing {:?} tags is not supported",
+        other
+      ))),
+    };
+
+    // the cached entry still holds the old tags
+    if written.is_ok() {
+      invalidate_track(&file_path);
+    }
+
+    return written;
+  })
+  .await
+  .map_err(|e| Error::Tag(e.to_string()))?;
+}
+
+fn write_vorbis_comments(path: &str, args: &[FrameArgs]) -> Result<()> {
+  let mut file = File::open(path).map_err(|e| Error::FileSystem(e.to_string()))?;
+  let options = ParseOptions::new().read_properties(false);
+  let file_type = guess_file_type(path)?;
+
+  // FLAC keeps pictures in their own blocks rather than in the comments, and
+  // saving the comments alone would drop them
+  if file_type == Some(FileType::Flac) {
+    let mut flac = FlacFile::read_from(&mut file, options).map_err(tag_error)?;
+
+    let mut comments = flac.remove_vorbis_comments().unwrap_or_default();
+    apply_vorbis_args(&mut comments, args);
+    flac.set_vorbis_comments(comments);
+
+    // lofty keeps the trailing padding block as it is, so the file is written
+    // in memory first and the padding takes up the change in size
+    let mut original = Vec::new();
+    file
+      .rewind()
+      .and_then(|_| file.read_to_end(&mut original))
+      .map_err(|e| Error::FileSystem(e.to_string()))?;
+    drop(file);
+
+    let mut written = Cursor::new(original.clone());
+    flac
+      .save_to(&mut written, write_options())
+      .map_err(tag_error)?;
+    let written = fit_flac_padding(&original, written.into_inner());
+
+    return std::fs::write(path, written).map_err(|e| Error::FileSystem(e.to_string()));
+  }
+
+  let mut comments = match file_type {
+    Some(FileType::Vorbis) => VorbisFile::read_from(&mut file, options)
+      .map_err(tag_error)?
+      .vorbis_comments()
+      .clone(),
+    Some(FileType::Opus) => OpusFile::read_from(&mut file, options)
+      .map_err(tag_error)?
+      .vorbis_comments()
+      .clone(),
+    Some(FileType::Speex) => SpeexFile::read_from(&mut file, options)
+      .map_err(tag_error)?
+      .vorbis_comments()
+      .clone(),
+    _ => {
+      return Err(Error::Tag(format!("{} can't hold Vorbis comments", path)));
+    }
+  };
+  drop(file);
+
+  apply_vorbis_args(&mut comments, args);
+
+  return comments
+    .save_to_path(path, write_options())
+    .map_err(tag_error);
+}
+
+fn apply_vorbis_args(comments: &mut VorbisComments, args: &[FrameArgs]) {
+  for arg in args {
+    match arg.frame.as_str() {
+      "TRCK" => set_vorbis_pair(comments, &arg.value, "TRACKNUMBER", "TRACKTOTAL"),
+      "TPOS" => set_vorbis_pair(comments, &arg.value, "DISCNUMBER", "DISCTOTAL"),
+      frame => {
+        let key = FIELDS
+          .iter()
+          .find(|(id, _, _)| *id == frame)
+          .map_or_else(|| frame.to_ascii_uppercase(), |(_, key, _)| key.to_string());
+
+        comments.remove(&key).for_each(drop);
+        if !arg.value.is_empty() {
+          comments.push(key, arg.value.clone());
+        }
+      }
+    }
+  }
+}
+
+fn set_vorbis_pair(comments: &mut VorbisComments, value: &str, number_key: &str, total_key: &str) {
+  let (number, total) = split_pair(value);
+
+  for (key, value) in [(number_key, number), (total_key, total)] {
+    comments.remove(key).for_each(drop);
+    if let Some(value) = value {
+      comments.push(key.to_string(), value.to_string());
+    }
+  }
+}
+
+fn write_ilst(path: &str, args: &[FrameArgs]) -> Result<()> {
+  if guess_file_type(path)? != Some(FileType::Mp4) {
+    return Err(Error::Tag(format!("{} is not an MP4 file", path)));
+  }
+
+  let mut file = File::open(path).map_err(|e| Error::FileSystem(e.to_string()))?;
+  let mut ilst = Mp4File::read_from(&mut file, ParseOptions::new().read_properties(false))
+    .map_err(tag_error)?
+    .ilst()
+    .cloned()
+    .unwrap_or_default();
+  drop(file);
+
+  for arg in args {
+    match arg.frame.as_str() {
+      "TRCK" => {
+        let (number, total) = split_pair(&arg.value);
+        match number.and_then(|n| n.parse().ok()) {
+          Some(number) => ilst.set_track(number),
+          None => ilst.remove_track(),
+        }
+        match total.and_then(|n| n.parse().ok()) {
+          Some(total) => ilst.set_track_total(total),
+          None => ilst.remove_track_total(),
+        }
+      }
+      "TPOS" => {
+        let (number, total) = split_pair(&arg.value);
+        match number.and_then(|n| n.parse().ok()) {
+          Some(number) => ilst.set_disk(number),
+          None => ilst.remove_disk(),
+        }
+        match total.and_then(|n| n.parse().ok()) {
+          Some(total) => ilst.set_disk_total(total),
+          None => ilst.remove_disk_total(),
+        }
+      }
+      frame => {
+        let ident = get_atom_ident(frame);
+
+        ilst.remove(&ident).for_each(drop);
+        if !arg.value.is_empty() {
+          ilst.insert(Atom::new(ident, AtomData::UTF8(arg.value.clone())));
+        }
+      }
+    }
+  }
+
+  return ilst.save_to_path(path, write_options()).map_err(tag_error);
+}
+
+fn get_atom_ident(frame: &str) -> AtomIdent<'static> {
+  let name = match FIELDS.iter().find(|(id, _, _)| *id == frame) {
+    Some((_, _, Some(fourcc))) => return AtomIdent::Fourcc(**fourcc),
+    Some((_, key, None)) => key.to_string(),
+    None => frame.to_ascii_uppercase(),
+  };
+
+  return AtomIdent::Freeform {
+    mean: Cow::Borrowed(ITUNES_MEAN),
+    name: Cow::Owned(name),
+  };
+}
+
+/// Splits ID3v2's `number/total` into its parts, dropping empty ones.
+fn split_pair(value: &str) -> (Option<&str>, Option<&str>) {
+  let (number, total) = value.split_once('/').unwrap_or((value, ""));
+
+  return (non_empty(number), non_empty(total));
+}
+
+fn non_empty(part: &str) -> Option<&str> {
+  let part = part.trim();
+  return (!part.is_empty()).then_some(part);
+}
+
+fn guess_file_type(path: &str) -> Result<Option<FileType>> {
+  let probe = Probe::open(path)
+    .map_err(|e| Error::FileSystem(e.to_string()))?
+    .guess_file_type()
+    .map_err(|e| Error::FileSystem(e.to_string()))?;
+
+  return Ok(probe.file_type());
+}
+
+// shared by every format: padding a file already has is reused and none is
+// added, so a tag edit never makes a file bigger than the edit itself
+fn write_options() -> WriteOptions {
+  return WriteOptions::new().preferred_padding(0);
+}
+
+// a FLAC metadata block's length is 24 bits
+const MAX_FLAC_BLOCK_LEN: usize = (1 << 24) - 1;
+
+/// Where the audio frames of a FLAC file start, and the offset and length of
+/// the last metadata block when it is padding.
+struct FlacLayout {
+  audio_start: usize,
+  trailing_padding: Option<(usize, usize)>,
+}
+
+impl FlacLayout {
+  fn read(bytes: &[u8]) -> Option<Self> {
+    if !bytes.starts_with(b"fLaC") {
+      return None;
+    }
+
+    let mut offset = 4;
+    loop {
+      let header = bytes.get(offset..offset + 4)?;
+      let is_last = header[0] & 0x80 != 0;
+      let is_padding = header[0] & 0x7f == 1;
+      let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+      let start = offset;
+      offset += 4 + len;
+
+      if is_last {
+        return Some(Self {
+          audio_start: offset,
+          trailing_padding: is_padding.then_some((start, len)),
+        });
+      }
+    }
+  }
+}
+
+/// Grows or shrinks the trailing padding block of `written` by however much
+/// the metadata changed from `original`, so the audio stays where it was.
+/// Left alone when there is no padding or not enough of it.
+fn fit_flac_padding(original: &[u8], mut written: Vec<u8>) -> Vec<u8> {
+  let (Some(before), Some(after)) = (FlacLayout::read(original), FlacLayout::read(&written)) else {
+    return written;
+  };
+  let Some((header, len)) = after.trailing_padding else {
+    return written;
+  };
+
+  let Some(new_len) = (len + before.audio_start).checked_sub(after.audio_start) else {
+    return written;
+  };
+  if new_len == len || new_len > MAX_FLAC_BLOCK_LEN {
+    return written;
+  }
+
+  let content = header + 4;
+  written.splice(content..content + len, std::iter::repeat_n(0, new_len));
+  written[header + 1..content].copy_from_slice(&(new_len as u32).to_be_bytes()[1..]);
+
+  return written;
+}
+
+fn tag_error(error: LoftyError) -> Error {
+  return Error::Tag(error.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use lofty::ogg::OggPictureStorage;
+
+  // silent FLAC with a title and artist, a front cover PICTURE block and 512
+  // bytes of trailing padding
+  const FLAC_FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../test/fixtures/vorbis-comments-cover.flac"
+  );
+
+  fn read_flac(path: &Path) -> FlacFile {
+    let mut file = File::open(path).unwrap();
+    return FlacFile::read_from(&mut file, ParseOptions::new()).unwrap();
+  }
+
+  #[test]
+  fn flac_comments_round_trip_with_cover_and_padding() {
+    let dir = std::env::temp_dir().join(format!("swim-tags-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cover.flac");
+    std::fs::copy(FLAC_FIXTURE, &path).unwrap();
+
+    let args = [
+      FrameArgs {
+        frame: "TIT2".to_string(),
+        value: "New".to_string(),
+      },
+      FrameArgs {
+        frame: "TRCK".to_string(),
+        value: "3/12".to_string(),
+      },
+    ];
+    write_vorbis_comments(path.to_str().unwrap(), &args).unwrap();
+
+    let original = read_flac(Path::new(FLAC_FIXTURE));
+    let written = read_flac(&path);
+    let comments = written.vorbis_comments().unwrap();
+
+    assert_eq!(comments.title().as_deref(), Some("New"));
+    assert_eq!(comments.artist().as_deref(), Some("Someone"));
+    assert_eq!(comments.get("TRACKNUMBER"), Some("3"));
+    assert_eq!(comments.get("TRACKTOTAL"), Some("12"));
+    assert_eq!(written.pictures().len(), 1);
+    assert_eq!(written.pictures(), original.pictures());
+
+    // the added comments come out of the trailing padding
+    let added = ["TRACKNUMBER=3", "TRACKTOTAL=12"]
+      .iter()
+      .map(|comment| 4 + comment.len())
+      .sum::<usize>();
+    let original_bytes = std::fs::read(FLAC_FIXTURE).unwrap();
+    let written_bytes = std::fs::read(&path).unwrap();
+    let before = FlacLayout::read(&original_bytes).unwrap();
+    let after = FlacLayout::read(&written_bytes).unwrap();
+
+    assert_eq!(written_bytes.len(), original_bytes.len());
+    assert_eq!(after.audio_start, before.audio_start);
+    assert_eq!(
+      after.trailing_padding.map(|(_, len)| len),
+      before.trailing_padding.map(|(_, len)| len - added)
+    );
+    assert_eq!(
+      written_bytes[after.audio_start..],
+      original_bytes[before.audio_start..]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -1,3 +1,6 @@
+use crate::error::{Error, Result};
+use std::time::UNIX_EPOCH;
+
 pub fn get_track_identity_key(title: Option<&str>, artist: Option<&str>) -> Option<String> {
   let t = title?.trim().to_lowercase();
   let a = artist?.trim().to_lowercase();
@@ -10,3 +13,28 @@ pub fn get_track_identity_key(title: Option<&str>, artist: Option<&str>) -> Opti
     a
   ))
 }
+
+/// Size and modification time, used to tell whether a cached result still
+/// belongs to the file on disk.
+pub struct FileSignature {
+  pub size: i64,
+  pub modified_at: i64,
+}
+
+impl FileSignature {
+  pub fn read(path: &str) -> Result<Self> {
+    let metadata = std::fs::metadata(path)
+      .map_err(|e| Error::FileSystem(format!("failed to read metadata of {}: {}", path, e)))?;
+
+    let modified_at = metadata
+      .modified()
+      .ok()
+      .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+      .map_or(0, |duration| duration.as_secs() as i64);
+
+    return Ok(Self {
+      size: metadata.len() as i64,
+      modified_at,
+    });
+  }
+}
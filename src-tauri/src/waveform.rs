@@ -1,173 +1,610 @@
+use crate::decoder::{decode_interleaved, GaplessDecoder};
 use crate::error::{Error, Result};
-use std::{ffi::OsStr, path::Path};
-use symphonia::core::{
-  audio::SampleBuffer,
-  codecs::{DecoderOptions, CODEC_TYPE_NULL},
-  errors::Error as SymphoniaError,
-  formats::FormatOptions,
-  io::MediaSourceStream,
-  meta::MetadataOptions,
-  probe::Hint,
-};
-use tauri::{async_runtime::spawn_blocking, AppHandle, Manager};
+use crate::waveform_cache::WaveformCache;
+use kira::sound::streaming::Decoder;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use std::collections::VecDeque;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{async_runtime::spawn_blocking, AppHandle};
+use tauri_specta::Event;
+
+// frames per bin of the finest pyramid level, about 6 ms at 44.1 kHz
+const BASE_BIN_FRAMES: usize = 256;
+// each level merges this many bins of the one below
+const LEVEL_FACTOR: usize = 4;
+// bumped whenever the cached layout of a pyramid changes
+const PYRAMID_FORMAT: u32 = 2;
+// pyramids kept in memory, most recently used last
+const PYRAMID_CACHE_SIZE: usize = 4;
+const MAX_RANGE_BINS: usize = 65536;
+// bin size the waveform view requests, pre-generated ahead of time
+const DEFAULT_BIN_SIZE: usize = 2048;
+
+// partial peaks are emitted at most this often while decoding
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+// path of the newest `get_waveform` request, decodes of other paths stop
+static CURRENT_WAVEFORM: Mutex<Option<String>> = Mutex::new(None);
+
+// cache key and pyramid, most recently used last
+type PyramidCache = VecDeque<(String, Arc<PeakPyramid>)>;
+
+static PYRAMID_CACHE: LazyLock<Mutex<PyramidCache>> = LazyLock::new(|| Mutex::new(VecDeque::new()));
+
+/// Min, max and RMS of one channel, one entry per bin.
+#[derive(Serialize, Deserialize, Type, Clone, Debug, Default)]
+pub struct ChannelPeaks {
+  pub min: Vec<f32>,
+  pub max: Vec<f32>,
+  pub rms: Vec<f32>,
+}
 
-#[tauri::command]
-#[specta::specta]
-pub async fn get_waveform(
-  app_handle: AppHandle<tauri::Wry>,
-  path: String,
-  bin_size: f32,
-) -> Result<Vec<f32>> {
-  spawn_blocking(move || {
-    let cache_dir = app_handle
-      .app_handle()
-      .path()
-      .app_cache_dir()
-      .map_err(|_| Error::FileSystem("failed to get cache directory".to_string()))?;
+#[derive(Serialize, Deserialize, Type, Clone, Debug)]
+pub struct WaveformRange {
+  pub sample_rate: u32,
+  /// Time of the first bin, in seconds.
+  pub start: f64,
+  /// Seconds covered by each bin.
+  pub bin_duration: f64,
+  /// One entry per channel of the file.
+  pub channels: Vec<ChannelPeaks>,
+}
+
+/// Peaks decoded so far by `get_waveform`, in its min/max pair format.
+#[derive(Serialize, Clone, Deserialize, Type, Debug, Event)]
+pub struct WaveformProgress {
+  pub path: String,
+  /// Index of the first pair in `peaks`.
+  pub offset: u32,
+  /// Pairs completed since the previous event.
+  pub peaks: Vec<f32>,
+  /// Pairs expected once the whole file is decoded.
+  pub total: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Peak {
+  min: f32,
+  max: f32,
+  // f64 so long bins don't lose their quiet samples to rounding
+  sum_squares: f64,
+  frames: u32,
+}
+
+impl Peak {
+  const EMPTY: Peak = Peak {
+    min: f32::INFINITY,
+    max: f32::NEG_INFINITY,
+    sum_squares: 0.0,
+    frames: 0,
+  };
+
+  fn add(&mut self, sample: f32) {
+    self.min = self.min.min(sample);
+    self.max = self.max.max(sample);
+    self.sum_squares += (sample as f64).powi(2);
+    self.frames += 1;
+  }
 
-    let cache_path = build_cache_path(&path, &cache_dir);
-    if let Ok(cached_waveform) = std::fs::read(&cache_path) {
-      let cached_waveform = serde_json::from_slice::<Vec<f32>>(&cached_waveform)
-        .map_err(|_| Error::Waveform("failed to parse cached waveform".to_string()))?;
+  fn merge(&mut self, other: &Peak) {
+    self.min = self.min.min(other.min);
+    self.max = self.max.max(other.max);
+    self.sum_squares += other.sum_squares;
+    self.frames += other.frames;
+  }
+}
 
-      return Ok(cached_waveform);
+impl ChannelPeaks {
+  fn push(&mut self, peak: &Peak) {
+    if peak.frames == 0 {
+      self.min.push(0.0);
+      self.max.push(0.0);
+      self.rms.push(0.0);
+      return;
     }
 
-    let src = std::fs::File::open(&path)
-      .map_err(|e| Error::FileSystem(format!("failed to open media: {}", e)))?;
+    self.min.push(peak.min);
+    self.max.push(peak.max);
+    self
+      .rms
+      .push((peak.sum_squares / peak.frames as f64).sqrt() as f32);
+  }
+}
 
-    let mss = MediaSourceStream::new(Box::new(src), Default::default());
+/// Per-channel peaks at several resolutions. Level `n` holds bins of
+/// `BASE_BIN_FRAMES * LEVEL_FACTOR^n` frames, channels interleaved.
+struct PeakPyramid {
+  sample_rate: u32,
+  channels: usize,
+  num_frames: usize,
+  levels: Vec<Vec<Peak>>,
+}
 
-    let ext = get_extension_from_filename(&path).ok_or(Error::Waveform(format!(
-      "failed to get extension from filename: {}",
-      path
-    )))?;
-    let mut hint = Hint::new();
-    hint.with_extension(ext);
+impl PeakPyramid {
+  fn cache_params() -> String {
+    return format!(
+      "pyramid:{}:{}:{}",
+      PYRAMID_FORMAT, BASE_BIN_FRAMES, LEVEL_FACTOR
+    );
+  }
 
-    let meta_opts: MetadataOptions = Default::default();
-    let fmt_opts: FormatOptions = FormatOptions {
-      prebuild_seek_index: true,
-      seek_index_fill_rate: 100,
-      enable_gapless: true,
-    };
+  /// Little-endian header of sample rate, channels, frames and level count,
+  /// then each level as a bin count followed by min, max, sum of squares and
+  /// frame count per bin.
+  fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend(self.sample_rate.to_le_bytes());
+    bytes.extend((self.channels as u32).to_le_bytes());
+    bytes.extend((self.num_frames as u64).to_le_bytes());
+    bytes.extend((self.levels.len() as u32).to_le_bytes());
+
+    for level in &self.levels {
+      bytes.extend((level.len() as u32).to_le_bytes());
+      for peak in level {
+        bytes.extend(peak.min.to_le_bytes());
+        bytes.extend(peak.max.to_le_bytes());
+        bytes.extend(peak.sum_squares.to_le_bytes());
+        bytes.extend(peak.frames.to_le_bytes());
+      }
+    }
 
-    let probed = symphonia::default::get_probe()
-      .format(&hint, mss, &fmt_opts, &meta_opts)
-      .map_err(|_| Error::Waveform("failed to probe format".to_string()))?;
-    let mut format = probed.format;
+    return bytes;
+  }
 
-    let track = format
-      .tracks()
-      .iter()
-      .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-      .ok_or(Error::Waveform("no supported audio tracks".to_string()))?;
+  fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+    let sample_rate = read_u32(&mut bytes)?;
+    let channels = read_u32(&mut bytes)? as usize;
+    let num_frames = read_u64(&mut bytes)? as usize;
+    let level_count = read_u32(&mut bytes)?;
+
+    let mut levels = Vec::new();
+    for _ in 0..level_count {
+      let len = read_u32(&mut bytes)? as usize;
+      let mut level = Vec::with_capacity(len.min(bytes.len() / 20));
+      for _ in 0..len {
+        level.push(Peak {
+          min: f32::from_bits(read_u32(&mut bytes)?),
+          max: f32::from_bits(read_u32(&mut bytes)?),
+          sum_squares: f64::from_bits(read_u64(&mut bytes)?),
+          frames: read_u32(&mut bytes)?,
+        });
+      }
+      levels.push(level);
+    }
 
-    let dec_opts: DecoderOptions = Default::default();
+    if channels == 0 || levels.is_empty() {
+      return None;
+    }
 
-    let mut decoder = symphonia::default::get_codecs()
-      .make(&track.codec_params, &dec_opts)
-      .map_err(|_| Error::Waveform("unsupported codec".to_string()))?;
+    return Some(Self {
+      sample_rate,
+      channels,
+      num_frames,
+      levels,
+    });
+  }
 
-    let track_id = track.id;
+  /// Builds every level from a single decode of the file.
+  fn build(path: &str, keep_going: impl Fn() -> bool) -> Result<Self> {
+    let mut base: Vec<Peak> = Vec::new();
+    let mut current: Vec<Peak> = Vec::new();
+    let mut sample_rate = 0;
+    let mut channels = 0;
+    let mut num_frames = 0;
+
+    decode_interleaved(path, |spec, samples| {
+      if !keep_going() {
+        return Err(Error::Cancelled(format!("peak pyramid of {}", path)));
+      }
 
-    let mut all_samples: Vec<f32> = Vec::with_capacity(bin_size as usize);
+      sample_rate = spec.rate;
+      channels = spec.channels.count();
+      if current.len() != channels {
+        current = vec![Peak::EMPTY; channels];
+      }
 
-    loop {
-      let packet = match format.next_packet() {
-        Ok(packet) => packet,
-        Err(SymphoniaError::ResetRequired) => {
-          return Err(Error::Waveform("reset required".to_string()));
+      for frame in samples.chunks_exact(channels) {
+        for (peak, sample) in current.iter_mut().zip(frame) {
+          peak.add(*sample);
         }
-        Err(SymphoniaError::IoError(_)) => break,
-        Err(err) => {
-          return Err(Error::Waveform(format!(
-            "failed to get next packet: {}",
-            err
-          )));
-        }
-      };
 
-      while !format.metadata().is_latest() {
-        format.metadata().pop();
+        num_frames += 1;
+        if num_frames % BASE_BIN_FRAMES == 0 {
+          base.append(&mut current);
+          current = vec![Peak::EMPTY; channels];
+        }
       }
 
-      if packet.track_id() != track_id {
-        continue;
-      }
+      return Ok(());
+    })?;
+
+    if current.first().is_some_and(|peak| peak.frames > 0) {
+      base.append(&mut current);
+    }
+
+    let mut levels = vec![base];
+    while let Some(below) = levels
+      .last()
+      .filter(|level| level.len() > channels * LEVEL_FACTOR)
+    {
+      let level: Vec<Peak> = below
+        .chunks(channels * LEVEL_FACTOR)
+        .flat_map(|group| {
+          (0..channels).map(move |channel| {
+            let mut peak = Peak::EMPTY;
+            for bin in group.iter().skip(channel).step_by(channels) {
+              peak.merge(bin);
+            }
+            peak
+          })
+        })
+        .collect();
+      levels.push(level);
+    }
 
-      match decoder.decode(&packet) {
-        Ok(decoded) => {
-          let spec = *decoded.spec();
+    return Ok(Self {
+      sample_rate,
+      channels,
+      num_frames,
+      levels,
+    });
+  }
 
-          // [L,L,R,R] -> [L,R]
-          let mut buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
-          buf.copy_interleaved_ref(decoded);
+  /// Merges the coarsest level that is still finer than `frames_per_bin`
+  /// into bins of that size.
+  fn range(&self, start_frame: usize, bins: usize, frames_per_bin: f64) -> Vec<ChannelPeaks> {
+    let mut level_index = 0;
+    let mut level_frames = BASE_BIN_FRAMES;
+    while level_index + 1 < self.levels.len()
+      && (level_frames * LEVEL_FACTOR) as f64 <= frames_per_bin
+    {
+      level_index += 1;
+      level_frames *= LEVEL_FACTOR;
+    }
 
-          let samples = buf.samples();
-          // convert to mono
-          for frame in samples.chunks(spec.channels.count()) {
-            let mono_sample: f32 = frame.iter().sum::<f32>() / frame.len() as f32;
-            all_samples.push(mono_sample);
-          }
+    let level = &self.levels[level_index];
+    let level_bins = level.len() / self.channels.max(1);
+    let mut channels = vec![ChannelPeaks::default(); self.channels];
+
+    for bin in 0..bins {
+      let from = start_frame as f64 + bin as f64 * frames_per_bin;
+      let first = (from / level_frames as f64) as usize;
+      let last = (((from + frames_per_bin) / level_frames as f64).ceil() as usize)
+        .max(first + 1)
+        .min(level_bins);
+
+      for (channel, peaks) in channels.iter_mut().enumerate() {
+        let mut peak = Peak::EMPTY;
+        for index in first..last {
+          peak.merge(&level[index * self.channels + channel]);
         }
-        Err(SymphoniaError::IoError(_)) => {
-          continue;
+        peaks.push(&peak);
+      }
+    }
+
+    return channels;
+  }
+}
+
+/// Peaks between `start` and `end` seconds at `pixels_per_second`, one bin
+/// per pixel. Zoomed out, bins come from a cached peak pyramid; zoomed in
+/// past its finest level the range is decoded directly, so every zoom level
+/// shows real peaks rather than interpolation.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_waveform_range(
+  app_handle: AppHandle<tauri::Wry>,
+  path: String,
+  start: f64,
+  end: f64,
+  pixels_per_second: f64,
+) -> Result<WaveformRange> {
+  spawn_blocking(move || {
+    let cache = WaveformCache::new(&app_handle)?;
+    let pyramid = get_pyramid(&cache, &path)?;
+    let sample_rate = pyramid.sample_rate as f64;
+
+    let start = start.max(0.0);
+    let end = end.min(pyramid.num_frames as f64 / sample_rate);
+    if end <= start || pixels_per_second <= 0.0 {
+      return Err(Error::Waveform("empty waveform range".to_string()));
+    }
+
+    let bins = (((end - start) * pixels_per_second).ceil() as usize).clamp(1, MAX_RANGE_BINS);
+    let frames_per_bin = (end - start) * sample_rate / bins as f64;
+    let start_frame = (start * sample_rate) as usize;
+
+    let channels = if frames_per_bin < BASE_BIN_FRAMES as f64 {
+      decode_range(&path, pyramid.channels, start_frame, bins, frames_per_bin)?
+    } else {
+      pyramid.range(start_frame, bins, frames_per_bin)
+    };
+
+    return Ok(WaveformRange {
+      sample_rate: pyramid.sample_rate,
+      start,
+      bin_duration: frames_per_bin / sample_rate,
+      channels,
+    });
+  })
+  .await
+  .map_err(|e| Error::Waveform(e.to_string()))?
+}
+
+/// Pyramid for `path` from memory, the disk cache, or a fresh decode.
+fn get_pyramid(cache: &WaveformCache, path: &str) -> Result<Arc<PeakPyramid>> {
+  let key = WaveformCache::key(path, &PeakPyramid::cache_params())?;
+
+  {
+    let mut pyramids = PYRAMID_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    let index = pyramids.iter().position(|(k, _)| *k == key);
+    if let Some(entry) = index.and_then(|index| pyramids.remove(index)) {
+      let pyramid = entry.1.clone();
+      pyramids.push_back(entry);
+      return Ok(pyramid);
+    }
+  }
+
+  let cached = cache
+    .read(&key)
+    .and_then(|bytes| PeakPyramid::from_bytes(&bytes));
+  let pyramid = match cached {
+    Some(pyramid) => Arc::new(pyramid),
+    None => {
+      let pyramid = PeakPyramid::build(path, || true)?;
+      cache.write(&key, &pyramid.to_bytes());
+      Arc::new(pyramid)
+    }
+  };
+
+  let mut pyramids = PYRAMID_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+  pyramids.push_back((key, pyramid.clone()));
+  while pyramids.len() > PYRAMID_CACHE_SIZE {
+    pyramids.pop_front();
+  }
+
+  return Ok(pyramid);
+}
+
+/// Decodes just the requested range, for bins finer than the pyramid's base.
+/// Like the pyramid it goes through `decode_interleaved`, so every channel of
+/// the file is kept, and stops as soon as the last bin is filled.
+fn decode_range(
+  path: &str,
+  channels: usize,
+  start_frame: usize,
+  bins: usize,
+  frames_per_bin: f64,
+) -> Result<Vec<ChannelPeaks>> {
+  let mut peaks = vec![ChannelPeaks::default(); channels];
+  let mut current = vec![Peak::EMPTY; channels];
+  let mut position = 0;
+  let mut bin = 0;
+  let bin_end = |bin: usize| start_frame as f64 + (bin + 1) as f64 * frames_per_bin;
+
+  let decoded = decode_interleaved(path, |spec, samples| {
+    for frame in samples.chunks_exact(spec.channels.count().max(1)) {
+      if position >= start_frame {
+        for (peak, sample) in current.iter_mut().zip(frame) {
+          peak.add(*sample);
         }
-        Err(SymphoniaError::DecodeError(_)) => {
-          continue;
+      }
+      position += 1;
+
+      if position as f64 >= bin_end(bin) {
+        for (channel, peak) in peaks.iter_mut().zip(current.iter_mut()) {
+          channel.push(peak);
+          *peak = Peak::EMPTY;
         }
-        Err(err) => {
-          return Err(Error::Waveform(format!("failed to decode packet: {}", err)));
+
+        bin += 1;
+        if bin == bins {
+          // the rest of the file isn't needed
+          return Err(Error::Cancelled(format!("waveform range of {}", path)));
         }
       }
     }
 
-    let mut waveform_data = Vec::new();
+    return Ok(());
+  });
+  match decoded {
+    Ok(()) | Err(Error::Cancelled(_)) => {}
+    Err(e) => return Err(e),
+  }
 
-    // chunk the samples into bins
-    for chunk in all_samples.chunks(bin_size as usize) {
-      let mut min = 0.0;
-      let mut max = 0.0;
+  // bins past the end of the file stay silent
+  while bin < bins {
+    for (channel, peak) in peaks.iter_mut().zip(current.iter_mut()) {
+      channel.push(peak);
+      *peak = Peak::EMPTY;
+    }
+    bin += 1;
+  }
 
-      for &sample in chunk {
-        if sample < min {
-          min = sample;
-        }
-        if sample > max {
-          max = sample;
-        }
+  return Ok(peaks);
+}
+
+/// Mono min/max pairs of `path`, emitting `WaveformProgress` as decoding
+/// goes so the waveform can fill in left to right. A request for another path
+/// cancels this one, which then returns `None`.
+#[tauri::command]
+#[specta::specta]
+pub async fn get_waveform(
+  app_handle: AppHandle<tauri::Wry>,
+  path: String,
+  bin_size: f32,
+) -> Result<Option<Vec<f32>>> {
+  spawn_blocking(move || {
+    let cache = WaveformCache::new(&app_handle)?;
+    let bin_size = (bin_size as usize).max(1);
+    let key = peaks_key(&path, bin_size)?;
+
+    if let Some(cached_waveform) = cache.read(&key) {
+      return Ok(Some(
+        cached_waveform
+          .chunks_exact(4)
+          .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+          .collect(),
+      ));
+    }
+
+    *CURRENT_WAVEFORM.lock().unwrap_or_else(|e| e.into_inner()) = Some(path.clone());
+    let is_current = || {
+      let current = CURRENT_WAVEFORM.lock().unwrap_or_else(|e| e.into_inner());
+      return current.as_deref() == Some(path.as_str());
+    };
+
+    let total = GaplessDecoder::from_file(&path)
+      .map_or(0, |decoder| decoder.num_frames().div_ceil(bin_size)) as u32;
+
+    let result = compute_peaks(&path, bin_size, is_current, |offset, peaks| {
+      let progress = WaveformProgress {
+        path: path.clone(),
+        offset: offset as u32,
+        peaks: peaks.to_vec(),
+        total,
+      };
+      if let Err(e) = progress.emit(&app_handle) {
+        log::error!("failed to emit waveform progress: {e}");
       }
+    });
+    let waveform_data = match result {
+      Ok(waveform_data) => waveform_data,
+      // the newer request fills in the waveform
+      Err(Error::Cancelled(_)) => return Ok(None),
+      Err(e) => return Err(e),
+    };
+
+    let waveform_data = ensure_min_samples(waveform_data, 2048);
+    cache.write(&key, &encode_peaks(&waveform_data));
+
+    Ok(Some(waveform_data))
+  })
+  .await
+  .map_err(|e| Error::Waveform(e.to_string()))?
+}
+
+/// Fills in whatever `path` is missing from the cache at the settings the
+/// player asks for, without touching the in-memory pyramids. Stops with
+/// `Error::Cancelled` once `keep_going` returns `false`.
+pub fn pregenerate(cache: &WaveformCache, path: &str, keep_going: impl Fn() -> bool) -> Result<()> {
+  let key = peaks_key(path, DEFAULT_BIN_SIZE)?;
+  if !cache.contains(&key) {
+    let waveform_data = compute_peaks(path, DEFAULT_BIN_SIZE, &keep_going, |_, _| {})?;
+    let waveform_data = ensure_min_samples(waveform_data, 2048);
+    cache.write(&key, &encode_peaks(&waveform_data));
+  }
+
+  let key = WaveformCache::key(path, &PeakPyramid::cache_params())?;
+  if !cache.contains(&key) {
+    let pyramid = PeakPyramid::build(path, &keep_going)?;
+    cache.write(&key, &pyramid.to_bytes());
+  }
+
+  return Ok(());
+}
+
+fn peaks_key(path: &str, bin_size: usize) -> Result<String> {
+  return WaveformCache::key(path, &format!("peaks:{}", bin_size));
+}
 
-      waveform_data.push((min * 100.0).round());
-      waveform_data.push((max * 100.0).round());
+fn encode_peaks(waveform_data: &[f32]) -> Vec<u8> {
+  return waveform_data.iter().flat_map(|v| v.to_le_bytes()).collect();
+}
+
+/// Mono min/max pairs over `bin_size` frames, scaled to ±100. Completed
+/// pairs are passed to `on_peaks` with the index of the first as decoding
+/// goes, and decoding stops with `Error::Cancelled` once `keep_going`
+/// returns `false`.
+fn compute_peaks(
+  path: &str,
+  bin_size: usize,
+  keep_going: impl Fn() -> bool,
+  mut on_peaks: impl FnMut(usize, &[f32]),
+) -> Result<Vec<f32>> {
+  let mut waveform_data = Vec::new();
+  let mut emitted = 0;
+  let mut last_emit = Instant::now();
+
+  let mut min: f32 = 0.0;
+  let mut max: f32 = 0.0;
+  let mut count = 0;
+
+  decode_interleaved(path, |spec, samples| {
+    if !keep_going() {
+      return Err(Error::Cancelled(format!(
+        "waveform of {} superseded by a newer request",
+        path
+      )));
+    }
+
+    // convert to mono
+    for frame in samples.chunks(spec.channels.count()) {
+      let mono_sample: f32 = frame.iter().sum::<f32>() / frame.len() as f32;
+      min = min.min(mono_sample);
+      max = max.max(mono_sample);
+      count += 1;
+
+      if count == bin_size {
+        waveform_data.push((min * 100.0).round());
+        waveform_data.push((max * 100.0).round());
+        min = 0.0;
+        max = 0.0;
+        count = 0;
+      }
     }
 
-    waveform_data = ensure_min_samples(waveform_data, 2048);
+    if last_emit.elapsed() >= PROGRESS_INTERVAL && waveform_data.len() > emitted {
+      on_peaks(emitted / 2, &waveform_data[emitted..]);
+      emitted = waveform_data.len();
+      last_emit = Instant::now();
+    }
 
-    std::fs::write(
-      &cache_path,
-      serde_json::to_vec(&waveform_data)
-        .map_err(|_| Error::Waveform("failed to serialize waveform".to_string()))?,
-    )
-    .map_err(|_| Error::Waveform("failed to write waveform to cache".to_string()))?;
+    return Ok(());
+  })?;
 
-    Ok(waveform_data)
+  if count > 0 {
+    waveform_data.push((min * 100.0).round());
+    waveform_data.push((max * 100.0).round());
+  }
+  if waveform_data.len() > emitted {
+    on_peaks(emitted / 2, &waveform_data[emitted..]);
+  }
+
+  return Ok(waveform_data);
+}
+
+/// Deletes every cached waveform, peak pyramid and spectrogram. Returns how
+/// many cache files were removed.
+#[tauri::command]
+#[specta::specta]
+pub async fn purge_waveform_cache(app_handle: AppHandle<tauri::Wry>) -> Result<u32> {
+  spawn_blocking(move || {
+    PYRAMID_CACHE
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .clear();
+
+    let waveforms = WaveformCache::new(&app_handle)?;
+    let spectrograms = WaveformCache::spectrograms(&app_handle)?;
+    return Ok(waveforms.purge() + spectrograms.purge());
   })
   .await
   .map_err(|e| Error::Waveform(e.to_string()))?
 }
 
-fn get_extension_from_filename(filename: &str) -> Option<&str> {
-  return Path::new(filename).extension().and_then(OsStr::to_str);
+fn read_u32(bytes: &mut &[u8]) -> Option<u32> {
+  let (value, rest) = bytes.split_first_chunk::<4>()?;
+  *bytes = rest;
+  return Some(u32::from_le_bytes(*value));
 }
 
-fn build_cache_path(file_path: &str, cache_dir: &Path) -> String {
-  let hash = format!("{:x}", md5::compute(file_path));
-  return cache_dir
-    .join(format!("{}-wf.json", hash))
-    .to_string_lossy()
-    .to_string();
+fn read_u64(bytes: &mut &[u8]) -> Option<u64> {
+  let (value, rest) = bytes.split_first_chunk::<8>()?;
+  *bytes = rest;
+  return Some(u64::from_le_bytes(*value));
 }
 
 fn ensure_min_samples(data: Vec<f32>, target_count: usize) -> Vec<f32> {
@@ -0,0 +1,238 @@
+use crate::error::{Error, Result};
+use crate::utils::FileSignature;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::SystemTime;
+use tauri::{AppHandle, Manager};
+
+// identifies the file layout, bump the version when it changes
+const MAGIC: &[u8; 4] = b"SWWF";
+const VERSION: u8 = 1;
+// oldest entries are evicted once a directory grows past its cap
+const MAX_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+const MAX_SPECTROGRAM_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
+// bytes in each cache directory, counted once and then kept up to date by
+// writes, so a write doesn't list the directory unless it has to evict
+static SIZES: LazyLock<Mutex<HashMap<PathBuf, u64>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Waveform data on disk, one file per source file state and parameters.
+/// Entries are keyed by path, size, modification time and whatever
+/// parameters shaped the data, so edited files and new settings miss instead
+/// of returning stale peaks. Spectrogram images live in a second instance
+/// with its own directory and cap.
+pub struct WaveformCache {
+  dir: PathBuf,
+  max_bytes: u64,
+  // name ending of the files older versions left in the cache root
+  legacy_suffix: &'static str,
+}
+
+impl WaveformCache {
+  pub fn new(app_handle: &AppHandle<tauri::Wry>) -> Result<Self> {
+    return Self::open(app_handle, "waveforms", MAX_CACHE_BYTES, "-wf.json");
+  }
+
+  pub fn spectrograms(app_handle: &AppHandle<tauri::Wry>) -> Result<Self> {
+    return Self::open(
+      app_handle,
+      "spectrograms",
+      MAX_SPECTROGRAM_CACHE_BYTES,
+      "-spec.png",
+    );
+  }
+
+  fn open(
+    app_handle: &AppHandle<tauri::Wry>,
+    name: &str,
+    max_bytes: u64,
+    legacy_suffix: &'static str,
+  ) -> Result<Self> {
+    let dir = app_handle
+      .path()
+      .app_cache_dir()
+      .map_err(|_| Error::FileSystem("failed to get cache directory".to_string()))?
+      .join(name);
+
+    fs::create_dir_all(&dir)
+      .map_err(|e| Error::FileSystem(format!("failed to create {} cache: {}", name, e)))?;
+
+    return Ok(Self {
+      dir,
+      max_bytes,
+      legacy_suffix,
+    });
+  }
+
+  /// Key for `path` in its current state, with `params` describing how the
+  /// data was produced.
+  pub fn key(path: &str, params: &str) -> Result<String> {
+    let signature = FileSignature::read(path)?;
+
+    return Ok(format!(
+      "{:x}",
+      md5::compute(format!(
+        "{}\0{}\0{}\0{}",
+        path, signature.size, signature.modified_at, params
+      ))
+    ));
+  }
+
+  fn entry_path(&self, key: &str) -> PathBuf {
+    return self.dir.join(format!("{}.wf", key));
+  }
+
+  pub fn contains(&self, key: &str) -> bool {
+    return self.entry_path(key).is_file();
+  }
+
+  /// Payload of the entry, or `None` if it is missing or from another
+  /// version. Hits are touched so eviction drops the least recently used.
+  pub fn read(&self, key: &str) -> Option<Vec<u8>> {
+    let path = self.entry_path(key);
+    let bytes = fs::read(&path).ok()?;
+
+    let payload = bytes
+      .strip_prefix(MAGIC.as_slice())
+      .and_then(|rest| rest.strip_prefix(&[VERSION]))?;
+
+    if let Err(e) = File::options()
+      .write(true)
+      .open(&path)
+      .and_then(|file| file.set_modified(SystemTime::now()))
+    {
+      log::warn!("failed to touch waveform cache entry: {e}");
+    }
+
+    return Some(payload.to_vec());
+  }
+
+  /// Stores `payload`, then evicts old entries if the cache is over its cap.
+  /// Best effort: failures are logged and the data is recomputed next time.
+  pub fn write(&self, key: &str, payload: &[u8]) {
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 1 + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.extend_from_slice(payload);
+
+    // write then rename so a reader never sees half an entry
+    let path = self.entry_path(key);
+    let tmp = path.with_extension("tmp");
+    let replaced = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+    if let Err(e) = fs::write(&tmp, &bytes).and_then(|_| fs::rename(&tmp, &path)) {
+      log::warn!("failed to write waveform cache entry: {e}");
+      let _ = fs::remove_file(&tmp);
+      return;
+    }
+
+    let mut sizes = SIZES.lock().unwrap_or_else(|e| e.into_inner());
+    let total = match sizes.get_mut(&self.dir) {
+      Some(total) => {
+        *total = (*total + bytes.len() as u64).saturating_sub(replaced);
+        *total
+      }
+      // first write since startup, the count already includes this entry
+      None => count_bytes(&self.dir),
+    };
+
+    let total = if total > self.max_bytes {
+      self.evict()
+    } else {
+      total
+    };
+    sizes.insert(self.dir.clone(), total);
+  }
+
+  /// Bytes that can still be written before old entries start being evicted.
+  pub fn room(&self) -> u64 {
+    let mut sizes = SIZES.lock().unwrap_or_else(|e| e.into_inner());
+    let total = *sizes
+      .entry(self.dir.clone())
+      .or_insert_with(|| count_bytes(&self.dir));
+
+    return self.max_bytes.saturating_sub(total);
+  }
+
+  /// Drops the least recently used entries until the cache is under its cap.
+  /// Returns the bytes left, recounted from the directory so the tracked
+  /// size can't drift from files removed behind its back.
+  fn evict(&self) -> u64 {
+    let mut entries = list_entries(&self.dir);
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    if total <= self.max_bytes {
+      return total;
+    }
+
+    entries.sort_by_key(|(_, _, modified)| *modified);
+    for (path, size, _) in entries {
+      if total <= self.max_bytes {
+        break;
+      }
+
+      match fs::remove_file(&path) {
+        Ok(()) => total -= size,
+        Err(e) => log::warn!("failed to evict waveform cache entry: {e}"),
+      }
+    }
+
+    return total;
+  }
+
+  /// Removes every entry, plus the files older versions left in the cache
+  /// directory. Returns how many files were removed.
+  pub fn purge(&self) -> u32 {
+    let mut removed = 0;
+
+    // recounted on the next write
+    SIZES
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .remove(&self.dir);
+
+    for (path, _, _) in list_entries(&self.dir) {
+      if fs::remove_file(&path).is_ok() {
+        removed += 1;
+      }
+    }
+
+    let legacy = self.dir.parent().and_then(|dir| fs::read_dir(dir).ok());
+    for entry in legacy.into_iter().flatten().flatten() {
+      let path = entry.path();
+      let is_legacy = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(self.legacy_suffix));
+
+      if is_legacy && fs::remove_file(&path).is_ok() {
+        removed += 1;
+      }
+    }
+
+    return removed;
+  }
+}
+
+fn count_bytes(dir: &Path) -> u64 {
+  return list_entries(dir).iter().map(|(_, size, _)| size).sum();
+}
+
+fn list_entries(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return Vec::new();
+  };
+
+  return entries
+    .flatten()
+    .filter_map(|entry| {
+      let metadata = entry.metadata().ok()?;
+      if !metadata.is_file() {
+        return None;
+      }
+
+      let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+      return Some((entry.path(), metadata.len(), modified));
+    })
+    .collect();
+}